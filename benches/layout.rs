@@ -0,0 +1,43 @@
+//! Compares `determine_layout()` with the exhaustive search it replaced.
+//!
+//! Run with `cargo bench --bench layout --features bench`.
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use rusl::layout::{determine_layout, determine_layout_exhaustive};
+use std::hint::black_box;
+
+const TERM_COLS: usize = 200;
+
+/// Deterministic pseudo-random file name lengths between 1 and 24.
+fn lens(n: usize) -> Vec<usize> {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    (0..n)
+        .map(|_| {
+            // xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % 24) as usize + 1
+        })
+        .collect()
+}
+
+fn bench_layout(c: &mut Criterion) {
+    for by_lines in [false, true] {
+        let name = if by_lines { "by_lines" } else { "by_cols" };
+        let mut group = c.benchmark_group(format!("determine_layout/{name}"));
+        for n in [1_000, 50_000, 500_000] {
+            let lens = lens(n);
+            group.bench_with_input(BenchmarkId::new("incremental", n), &lens, |b, lens| {
+                b.iter(|| determine_layout(by_lines, TERM_COLS, black_box(lens)))
+            });
+            group.bench_with_input(BenchmarkId::new("exhaustive", n), &lens, |b, lens| {
+                b.iter(|| determine_layout_exhaustive(by_lines, TERM_COLS, black_box(lens)))
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_layout);
+criterion_main!(benches);
@@ -0,0 +1,44 @@
+//! Compares stat-ing a directory's entries one at a time with batching them through io_uring.
+//!
+//! Run with `cargo bench --bench stat --features io-uring`.
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use rusl::uring::StatxRing;
+use std::fs;
+use std::hint::black_box;
+use std::path::{Path, PathBuf};
+
+fn bench_stat(c: &mut Criterion) {
+    let Ok(mut ring) = StatxRing::new() else {
+        eprintln!("io_uring statx is unavailable, skipping");
+        return;
+    };
+    let mut group = c.benchmark_group("stat");
+    for n in [100, 1_000, 10_000] {
+        let tmp = tempfile::tempdir().unwrap();
+        let paths = (0..n)
+            .map(|ind| {
+                let path = tmp.path().join(format!("file{ind}"));
+                fs::write(&path, "").unwrap();
+                path
+            })
+            .collect::<Vec<PathBuf>>();
+        let paths = paths.iter().map(PathBuf::as_path).collect::<Vec<&Path>>();
+        let dir = fs::File::open(tmp.path()).unwrap();
+
+        group.bench_with_input(BenchmarkId::new("sequential", n), &paths, |b, paths| {
+            b.iter(|| {
+                for path in paths {
+                    black_box(fs::symlink_metadata(path).ok());
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("io_uring", n), &paths, |b, paths| {
+            b.iter(|| black_box(ring.lstat_all(&dir, paths).unwrap()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_stat);
+criterion_main!(benches);
@@ -0,0 +1,278 @@
+//! POSIX ACLs of listed entries for `--acl`, read from the extended attributes they are
+//! stored in.
+
+use crate::xattr::XattrFile;
+use nix::unistd::{Gid, Group, Uid, User};
+use std::ffi::OsStr;
+use std::io::Result as IOResult;
+use std::os::fd::BorrowedFd;
+use std::path::Path;
+
+/// Extended attribute holding the ACL checked when accessing a file.
+const ACCESS_XATTR: &str = "system.posix_acl_access";
+/// Extended attribute holding the ACL inherited by entries created in a directory.
+const DEFAULT_XATTR: &str = "system.posix_acl_default";
+/// The only version of the extended attribute format.
+const XATTR_VERSION: u32 = 2;
+
+/// Who an ACL entry grants permissions to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclTag {
+    /// the owner of the file
+    UserObj,
+    /// the user with this uid
+    User(u32),
+    /// the group of the file
+    GroupObj,
+    /// the group with this gid
+    Group(u32),
+    /// the most permissions granted by any named entry or the group entry
+    Mask,
+    Other,
+}
+
+/// A single entry of an ACL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AclEntry {
+    pub tag: AclTag,
+    /// read, write and execute bits, in the same order as in a mode
+    pub perms: u16,
+}
+
+/// A list of ACL entries in the order the kernel stores them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Acl {
+    pub entries: Vec<AclEntry>,
+}
+
+impl Acl {
+    /// Parse the contents of an ACL extended attribute: a little-endian version, followed by
+    /// a tag, permissions and id for each entry. Returns `None` if it is malformed.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let (version, rest) = bytes.split_first_chunk::<4>()?;
+        if u32::from_le_bytes(*version) != XATTR_VERSION || rest.len() % 8 != 0 {
+            return None;
+        }
+        let entries = rest
+            .chunks_exact(8)
+            .map(|entry| {
+                let tag = u16::from_le_bytes([entry[0], entry[1]]);
+                let perms = u16::from_le_bytes([entry[2], entry[3]]);
+                let id = u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]);
+                let tag = match tag {
+                    0x01 => AclTag::UserObj,
+                    0x02 => AclTag::User(id),
+                    0x04 => AclTag::GroupObj,
+                    0x08 => AclTag::Group(id),
+                    0x10 => AclTag::Mask,
+                    0x20 => AclTag::Other,
+                    _ => return None,
+                };
+                Some(AclEntry { tag, perms })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self { entries })
+    }
+
+    /// Whether the ACL grants anything beyond the owner, group and other entries which are
+    /// equivalent to the mode of the file.
+    pub fn is_extended(&self) -> bool {
+        self.entries.len() > 3
+    }
+
+    fn mask(&self) -> Option<u16> {
+        self.entries
+            .iter()
+            .find(|entry| entry.tag == AclTag::Mask)
+            .map(|entry| entry.perms)
+    }
+
+    /// The entries in the format of `getfacl`, each preceded by `prefix`. Entries limited by
+    /// the mask are followed by the permissions they actually grant.
+    pub fn lines(&self, prefix: &str) -> Vec<String> {
+        let mask = self.mask();
+        self.entries
+            .iter()
+            .map(|entry| {
+                let name = |id: u32, user: bool| {
+                    let name = if user {
+                        User::from_uid(Uid::from_raw(id))
+                            .ok()
+                            .flatten()
+                            .map(|u| u.name)
+                    } else {
+                        Group::from_gid(Gid::from_raw(id))
+                            .ok()
+                            .flatten()
+                            .map(|g| g.name)
+                    };
+                    name.unwrap_or_else(|| id.to_string())
+                };
+                let (qualifier, masked) = match entry.tag {
+                    AclTag::UserObj => ("user:".to_string(), false),
+                    AclTag::User(uid) => (format!("user:{}", name(uid, true)), true),
+                    AclTag::GroupObj => ("group:".to_string(), true),
+                    AclTag::Group(gid) => (format!("group:{}", name(gid, false)), true),
+                    AclTag::Mask => ("mask:".to_string(), false),
+                    AclTag::Other => ("other:".to_string(), false),
+                };
+                let mut line = format!("{prefix}{qualifier}:{}", perms(entry.perms));
+                match mask {
+                    Some(mask) if masked && entry.perms & !mask != 0 => {
+                        line.push_str(&format!("  #effective:{}", perms(entry.perms & mask)));
+                    }
+                    _ => (),
+                }
+                line
+            })
+            .collect()
+    }
+}
+
+/// `rwx` with `-` in place of permissions which are not granted.
+fn perms(perms: u16) -> String {
+    [(4, 'r'), (2, 'w'), (1, 'x')]
+        .iter()
+        .map(|&(bit, c)| if perms & bit != 0 { c } else { '-' })
+        .collect()
+}
+
+/// The access and default ACLs of a file. Files without an access ACL, or on filesystems
+/// without ACLs, only have the permissions of their mode.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileAcl {
+    pub access: Option<Acl>,
+    pub default: Option<Acl>,
+}
+
+impl FileAcl {
+    /// Read the ACLs of `path` without following symlinks, relative to the open directory `dir`
+    /// if it is given. Malformed ACLs are left out.
+    pub fn read(dir: Option<BorrowedFd<'_>>, path: &Path) -> IOResult<Self> {
+        let file = XattrFile::open(dir, path)?;
+        let read = |name: &str| -> IOResult<Option<Acl>> {
+            Ok(file
+                .get(OsStr::new(name))?
+                .and_then(|bytes| Acl::parse(&bytes)))
+        };
+        Ok(Self {
+            access: read(ACCESS_XATTR)?,
+            default: read(DEFAULT_XATTR)?,
+        })
+    }
+
+    /// Whether the file has an ACL which grants more than its mode, or a default ACL. `ls`
+    /// marks these with `+` after the permissions.
+    pub fn is_present(&self) -> bool {
+        self.access.as_ref().is_some_and(Acl::is_extended) || self.default.is_some()
+    }
+
+    /// The access entries followed by the default entries, in the format of `getfacl`.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = self
+            .access
+            .as_ref()
+            .map_or_else(Vec::new, |acl| acl.lines(""));
+        if let Some(default) = &self.default {
+            lines.extend(default.lines("default:"));
+        }
+        lines
+    }
+}
+
+/// Encode `entries` of a tag, permissions and id in the extended attribute format, for tests.
+#[cfg(test)]
+pub(crate) fn encode(entries: &[(u16, u16, u32)]) -> Vec<u8> {
+    let mut bytes = XATTR_VERSION.to_le_bytes().to_vec();
+    for &(tag, perms, id) in entries {
+        bytes.extend(tag.to_le_bytes());
+        bytes.extend(perms.to_le_bytes());
+        bytes.extend(id.to_le_bytes());
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::display::test::{fixture, render};
+    use crate::lister::Lister;
+    use crate::xattr;
+    use std::fs::File;
+    use std::os::fd::AsFd;
+
+    #[test]
+    fn test_parse_acl() {
+        let bytes = encode(&[
+            (0x01, 6, u32::MAX),
+            (0x02, 7, 4_000_000),
+            (0x04, 4, u32::MAX),
+            (0x10, 5, u32::MAX),
+            (0x20, 0, u32::MAX),
+        ]);
+        let acl = Acl::parse(&bytes).unwrap();
+        assert!(acl.is_extended());
+        assert_eq!(acl.entries[1].tag, AclTag::User(4_000_000));
+        assert_eq!(
+            acl.lines(""),
+            [
+                "user::rw-",
+                "user:4000000:rwx  #effective:r-x",
+                "group::r--",
+                "mask::r-x",
+                "other::---",
+            ]
+        );
+        assert_eq!(Acl::parse(&bytes[..bytes.len() - 1]), None);
+        assert_eq!(Acl::parse(&encode(&[(0x40, 7, 0)])), None);
+
+        let minimal = encode(&[(0x01, 6, 0), (0x04, 4, 0), (0x20, 4, 0)]);
+        let acl = FileAcl {
+            access: Acl::parse(&minimal),
+            default: None,
+        };
+        assert!(!acl.is_present());
+    }
+
+    #[test]
+    fn test_read_acl() {
+        let tmp = fixture();
+        let acl = encode(&[
+            (0x01, 6, 0),
+            (0x02, 7, 4_000_000),
+            (0x04, 4, 0),
+            (0x10, 5, 0),
+            (0x20, 4, 0),
+        ]);
+        xattr::set(&tmp.path().join("alpha"), ACCESS_XATTR, &acl);
+        // entries of an open directory are read through it, by name
+        let dir = File::open(tmp.path()).unwrap();
+        let read = FileAcl::read(Some(dir.as_fd()), Path::new("alpha")).unwrap();
+        assert_eq!(read.access, Acl::parse(&acl));
+        assert_eq!(read.default, None);
+        let read = FileAcl::read(Some(dir.as_fd()), Path::new("beta.txt")).unwrap();
+        assert!(!read.is_present());
+
+        let files = ["alpha", "beta.txt"].map(|f| tmp.path().join(f));
+        let lister = Lister::new().paths(&files).long(true).color(false);
+        // the group permissions show the mask
+        let out = render(lister.clone());
+        let lines = out.lines().collect::<Vec<_>>();
+        assert!(lines[0].starts_with("-rw-r-xr--+ 1 "), "{out}");
+        assert!(lines[1].starts_with("-rw-r--r--  1 "), "{out}");
+
+        let out = render(lister.acl(true));
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[1..6],
+            [
+                "    user::rw-",
+                "    user:4000000:rwx  #effective:r-x",
+                "    group::r--",
+                "    mask::r-x",
+                "    other::r--",
+            ]
+        );
+        assert_eq!(lines.len(), 7, "{out}");
+    }
+}
@@ -0,0 +1,186 @@
+//! Inode flags of listed entries for `--attrs`, read with the `FS_IOC_GETFLAGS` ioctl and shown
+//! like `lsattr` does.
+
+use nix::fcntl::{OFlag, open, openat};
+use nix::sys::stat::Mode;
+use std::fmt;
+use std::fmt::Display;
+use std::os::fd::{AsRawFd, BorrowedFd, OwnedFd};
+use std::path::Path;
+
+/// The flags shown by `lsattr`, in the order it shows them.
+const FLAGS: &[(u32, char)] = &[
+    (0x0000_0001, 's'), // secure deletion
+    (0x0000_0002, 'u'), // undeletable
+    (0x0000_0008, 'S'), // synchronous updates
+    (0x0001_0000, 'D'), // synchronous directory updates
+    (0x0000_0010, 'i'), // immutable
+    (0x0000_0020, 'a'), // append only
+    (0x0000_0040, 'd'), // no dump
+    (0x0000_0080, 'A'), // no atime updates
+    (0x0000_0004, 'c'), // compressed
+    (0x0000_0800, 'E'), // encrypted
+    (0x0000_4000, 'j'), // data journalling
+    (0x0000_1000, 'I'), // indexed directory
+    (0x0000_8000, 't'), // no tail merging
+    (0x0002_0000, 'T'), // top of directory hierarchy
+    (0x0008_0000, 'e'), // extents
+    (0x0080_0000, 'C'), // no copy on write
+    (0x0200_0000, 'x'), // direct access
+    (0x1000_0000, 'N'), // inline data
+    (0x2000_0000, 'P'), // project hierarchy
+    (0x0010_0000, 'V'), // verity
+    (0x0000_0400, 'm'), // don't compress
+    (0x4000_0000, 'F'), // casefolded
+];
+
+/// The bit of the flag shown as `letter` by `lsattr`.
+fn flag_bit(letter: char) -> Option<u32> {
+    FLAGS
+        .iter()
+        .find(|&&(_, c)| c == letter)
+        .map(|&(bit, _)| bit)
+}
+
+/// The inode flags of a file, as set by `chattr`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InodeFlags(pub u32);
+
+impl InodeFlags {
+    /// Read the flags of `path` without following symlinks, relative to the open directory
+    /// `dir` if it is given. Returns `None` if the filesystem does not support flags. This opens
+    /// the file, so it should only be used for regular files and directories.
+    pub fn read(dir: Option<BorrowedFd<'_>>, path: &Path) -> Option<Self> {
+        let flags = OFlag::O_RDONLY
+            | OFlag::O_NONBLOCK
+            | OFlag::O_NOFOLLOW
+            | OFlag::O_NOCTTY
+            | OFlag::O_CLOEXEC;
+        let fd: OwnedFd = match (dir, path.file_name()) {
+            (Some(dir), Some(name)) => openat(dir, name, flags, Mode::empty()),
+            _ => open(path, flags, Mode::empty()),
+        }
+        .ok()?;
+        let mut bits: libc::c_int = 0;
+        // SAFETY: `FS_IOC_GETFLAGS` writes an int to the pointer, which is valid for writes
+        let res = unsafe { libc::ioctl(fd.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut bits) };
+        (res == 0).then_some(Self(bits as u32))
+    }
+
+    /// Whether every flag in `bits` is set.
+    pub fn contains(&self, bits: u32) -> bool {
+        self.0 & bits == bits
+    }
+}
+
+/// Displays the flags like `lsattr`, with a letter for each flag which is set and `-` for
+/// each one which is not.
+impl Display for InodeFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let letters = FLAGS
+            .iter()
+            .map(|&(bit, c)| if self.contains(bit) { c } else { '-' })
+            .collect::<String>();
+        write!(f, "{letters}")
+    }
+}
+
+/// Keeps entries by their inode flags, given as `lsattr` letters which must be set, or unset
+/// when preceded by `-`, e.g. `i-a` for immutable entries which are not append only. A `+`
+/// switches back to flags which must be set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AttrFilter {
+    required: u32,
+    excluded: u32,
+}
+
+impl AttrFilter {
+    /// Parse a filter, or describe the first letter which is not a known flag.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut filter = Self::default();
+        let mut set = true;
+        for c in spec.chars() {
+            match c {
+                '+' => set = true,
+                '-' => set = false,
+                c => {
+                    let bit = flag_bit(c).ok_or_else(|| format!("unknown attribute '{c}'"))?;
+                    if set {
+                        filter.required |= bit;
+                    } else {
+                        filter.excluded |= bit;
+                    }
+                }
+            }
+        }
+        Ok(filter)
+    }
+
+    /// Whether an entry with `flags` is kept. Entries whose flags are unknown only have the
+    /// flags which are not required.
+    pub fn matches(&self, flags: Option<InodeFlags>) -> bool {
+        let flags = flags.map_or(0, |flags| flags.0);
+        flags & self.required == self.required && flags & self.excluded == 0
+    }
+}
+
+/// Set the inode flags of `path`, for tests.
+#[cfg(test)]
+pub(crate) fn set(path: &Path, flags: u32) {
+    let fd = open(path, OFlag::O_RDONLY, Mode::empty()).unwrap();
+    let bits = flags as libc::c_int;
+    // SAFETY: `FS_IOC_SETFLAGS` reads an int from the pointer, which is valid for reads
+    let res = unsafe { libc::ioctl(fd.as_raw_fd(), libc::FS_IOC_SETFLAGS, &bits) };
+    assert_eq!(res, 0, "{}", std::io::Error::last_os_error());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::display::test::{fixture, render};
+    use crate::lister::Lister;
+
+    #[test]
+    fn test_read_flags() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("file");
+        std::fs::write(&path, "").unwrap();
+        let before = InodeFlags::read(None, &path).unwrap();
+        set(&path, before.0 | flag_bit('d').unwrap());
+        let flags = InodeFlags::read(None, &path).unwrap();
+        assert!(flags.to_string().starts_with("------d-"), "{flags}");
+        assert_eq!(flags.to_string().len(), FLAGS.len());
+
+        let filter = AttrFilter::parse("d-i").unwrap();
+        assert!(filter.matches(Some(flags)));
+        assert!(!filter.matches(Some(before)));
+        assert!(!filter.matches(None));
+        assert!(AttrFilter::parse("-i").unwrap().matches(None));
+        assert_eq!(
+            AttrFilter::parse("dz"),
+            Err("unknown attribute 'z'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_filter_and_show_flags() {
+        let tmp = fixture();
+        let beta = tmp.path().join("beta.txt");
+        let flags = InodeFlags::read(None, &beta).unwrap();
+        set(&beta, flags.0 | flag_bit('d').unwrap());
+        let lister = Lister::new()
+            .path(tmp.path())
+            .color(false)
+            .one_per_line(true);
+        let only = |spec: &str| lister.clone().attr_filter(AttrFilter::parse(spec).ok());
+        assert_eq!(render(only("d")), "beta.txt\n");
+        assert_eq!(render(only("-d")), "alpha\ndocs/\nlink\nrun.sh\nzeta\n");
+
+        let out = render(only("d").long(true).attrs(true));
+        let letters = InodeFlags(flags.0 | flag_bit('d').unwrap()).to_string();
+        assert!(
+            out.contains(&format!(" {letters} 8 Jun 15 2020 beta.txt\n")),
+            "{out}"
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Detection of whether the terminal has a light or dark background, used to pick the variant
+//! of the theme. The terminal is asked for its background colour with an OSC 11 query, and the
+//! answer is cached for the rest of the terminal session.
+
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use nix::sys::stat::fstat;
+use nix::unistd::getsid;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::AsFd;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use termion::get_tty;
+use termion::raw::IntoRawMode;
+
+/// Asks for the background colour, followed by a primary device attributes query, which every
+/// terminal answers. Terminals which do not support OSC 11 still answer the second query, so
+/// they do not leave us waiting.
+const QUERY: &[u8] = b"\x1b]11;?\x1b\\\x1b[c";
+
+/// Whether the terminal background is light or dark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    Light,
+    Dark,
+}
+
+impl Background {
+    /// The background with the colour `(r, g, b)`, which is light if its luma is over half.
+    /// Luma weighs the gamma-encoded channels like relative luminance weighs linear ones, and is
+    /// close enough to tell light from dark.
+    pub fn from_rgb((r, g, b): (u8, u8, u8)) -> Self {
+        let luma = 0.2126 * f64::from(r) + 0.7152 * f64::from(g) + 0.0722 * f64::from(b);
+        if luma > 127.5 {
+            Self::Light
+        } else {
+            Self::Dark
+        }
+    }
+
+    /// The name of the background, which is also the key of its variant in theme files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Light => "light",
+            Self::Dark => "dark",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [Self::Light, Self::Dark]
+            .into_iter()
+            .find(|background| background.name() == name)
+    }
+}
+
+/// Parse the colour from the answer to an OSC 11 query, `ESC ] 11 ; rgb:R/G/B` ended by
+/// `ESC \` or `BEL`, where each channel has 1 to 4 hex digits.
+fn parse_response(response: &[u8]) -> Option<(u8, u8, u8)> {
+    let start = response
+        .windows(4)
+        .position(|w| w == b"]11;")
+        .map(|i| i + 4)?;
+    let rest = &response[start..];
+    let end = rest.iter().position(|&b| b == b'\x1b' || b == b'\x07')?;
+    let spec = std::str::from_utf8(&rest[..end])
+        .ok()?
+        .strip_prefix("rgb:")?;
+    let channel = |hex: &str| {
+        if !(1..=4).contains(&hex.len()) {
+            return None;
+        }
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        let max = (1 << (4 * hex.len())) - 1;
+        Some((value * 255 / max) as u8)
+    };
+    match spec.split('/').collect::<Vec<_>>()[..] {
+        [r, g, b] => Some((channel(r)?, channel(g)?, channel(b)?)),
+        _ => None,
+    }
+}
+
+/// Whether `response` ends with the answer to the device attributes query, `ESC [ ? ... c`.
+fn is_complete(response: &[u8]) -> bool {
+    response
+        .windows(3)
+        .rposition(|w| w == b"\x1b[?")
+        .is_some_and(|i| response[i..].contains(&b'c'))
+}
+
+/// Read the answer to `QUERY` from `tty`, waiting until `deadline` for it to be complete.
+/// Returns `None` if it is not complete in time.
+fn read_response(tty: &mut File, deadline: Instant) -> Option<Vec<u8>> {
+    let mut response = Vec::new();
+    let mut buf = [0; 64];
+    while !is_complete(&response) {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let timeout = PollTimeout::try_from(remaining).ok()?;
+        let mut fds = [PollFd::new(tty.as_fd(), PollFlags::POLLIN)];
+        if poll(&mut fds, timeout).ok()? == 0 {
+            return None;
+        }
+        let n = tty.read(&mut buf).ok()?;
+        if n == 0 {
+            return None;
+        }
+        response.extend_from_slice(&buf[..n]);
+    }
+    Some(response)
+}
+
+/// Discard whatever `tty` has already received, without waiting for more.
+fn drain(tty: &mut File) {
+    let mut buf = [0; 64];
+    loop {
+        let mut fds = [PollFd::new(tty.as_fd(), PollFlags::POLLIN)];
+        if !matches!(poll(&mut fds, PollTimeout::ZERO), Ok(n) if n > 0) {
+            return;
+        }
+        if !matches!(tty.read(&mut buf), Ok(n) if n > 0) {
+            return;
+        }
+    }
+}
+
+/// Ask the terminal `tty` for its background colour, waiting at most `timeout` for the answer.
+/// Returns `None` if the terminal could not be asked, and `Some(None)` if it did not answer in
+/// time or answered without its background colour.
+fn query(tty: File, timeout: Duration) -> Option<Option<Background>> {
+    // raw mode stops the answer from being echoed and lets it be read before a newline
+    let mut tty = tty.into_raw_mode().ok()?;
+    tty.write_all(QUERY).ok()?;
+    tty.flush().ok()?;
+    let response = read_response(&mut tty, Instant::now() + timeout);
+    if response.is_none() {
+        // whatever part of a late answer has arrived would otherwise be echoed or read by the
+        // shell as typed input once raw mode is restored
+        drain(&mut tty);
+    }
+    Some(
+        response
+            .and_then(|response| parse_response(&response))
+            .map(Background::from_rgb),
+    )
+}
+
+/// The file caching the background of the terminal `tty` for the current session, in
+/// `$XDG_RUNTIME_DIR/rusl`, which is cleared when the user logs out. The session is identified
+/// by the terminal device and the session id, which belongs to the shell running in it.
+fn cache_path(tty: &File) -> Option<PathBuf> {
+    let runtime_dir = env::var_os("XDG_RUNTIME_DIR")?;
+    let device = fstat(tty.as_fd()).ok()?.st_rdev;
+    let session = getsid(None).ok()?;
+    Some(PathBuf::from(runtime_dir).join(format!("rusl/background-{device:x}-{session}")))
+}
+
+/// Detect the background of the controlling terminal, asking it with a `timeout` unless it was
+/// already detected in this terminal session. Returns `None` if there is no terminal or it does
+/// not report its background colour.
+pub fn detect(timeout: Duration) -> Option<Background> {
+    let tty = get_tty().ok()?;
+    let cache = cache_path(&tty);
+    if let Some(cached) = cache
+        .as_ref()
+        .and_then(|path| fs::read_to_string(path).ok())
+    {
+        return Background::from_name(&cached);
+    }
+    let background = query(tty, timeout)?;
+    // terminals which did not answer in time or without a colour are cached too, so they are not
+    // asked again
+    if let Some(path) = cache
+        && let Some(dir) = path.parent()
+        && fs::create_dir_all(dir).is_ok()
+    {
+        let _ = fs::write(path, background.map_or("unknown", Background::name));
+    }
+    background
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        let dark = b"\x1b]11;rgb:1c1c/1e1e/2626\x1b\\\x1b[?62;22c";
+        assert!(is_complete(dark));
+        assert_eq!(parse_response(dark), Some((0x1c, 0x1e, 0x26)));
+        assert_eq!(
+            parse_response(dark).map(Background::from_rgb),
+            Some(Background::Dark)
+        );
+
+        let light = b"\x1b]11;rgb:ffff/f6f/e3\x07\x1b[?1;2c";
+        assert_eq!(parse_response(light), Some((0xff, 0xf6, 0xe3)));
+        assert_eq!(
+            parse_response(light).map(Background::from_rgb),
+            Some(Background::Light)
+        );
+
+        // terminals without OSC 11 only answer the device attributes query
+        assert!(is_complete(b"\x1b[?6c"));
+        assert_eq!(parse_response(b"\x1b[?6c"), None);
+        assert!(!is_complete(b"\x1b]11;rgb:0/0/0\x1b\\\x1b[?6"));
+        assert_eq!(parse_response(b"\x1b]11;rgb:00000/0/0\x07"), None);
+        assert_eq!(parse_response(b"\x1b]11;rgb:0/0\x07"), None);
+    }
+}
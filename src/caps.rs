@@ -0,0 +1,242 @@
+//! File capabilities of listed entries for `--caps`, decoded from the `security.capability`
+//! extended attribute.
+
+use crate::xattr;
+use itertools::Itertools;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fmt::Display;
+use std::os::fd::BorrowedFd;
+use std::path::Path;
+
+/// Extended attribute holding the capabilities granted when executing a file.
+const CAPS_XATTR: &str = "security.capability";
+/// Bits of the first word of the extended attribute holding its revision.
+const REVISION_MASK: u32 = 0xff00_0000;
+/// Bit of the first word of the extended attribute set when permitted capabilities are also
+/// effective.
+const FLAGS_EFFECTIVE: u32 = 0x0000_0001;
+const REVISION_1: u32 = 0x0100_0000;
+const REVISION_2: u32 = 0x0200_0000;
+const REVISION_3: u32 = 0x0300_0000;
+
+/// Capability names without their `cap_` prefix, indexed by capability number.
+const CAP_NAMES: &[&str] = &[
+    "chown",
+    "dac_override",
+    "dac_read_search",
+    "fowner",
+    "fsetid",
+    "kill",
+    "setgid",
+    "setuid",
+    "setpcap",
+    "linux_immutable",
+    "net_bind_service",
+    "net_broadcast",
+    "net_admin",
+    "net_raw",
+    "ipc_lock",
+    "ipc_owner",
+    "sys_module",
+    "sys_rawio",
+    "sys_chroot",
+    "sys_ptrace",
+    "sys_pacct",
+    "sys_admin",
+    "sys_boot",
+    "sys_nice",
+    "sys_resource",
+    "sys_time",
+    "sys_tty_config",
+    "mknod",
+    "lease",
+    "audit_write",
+    "audit_control",
+    "setfcap",
+    "mac_override",
+    "mac_admin",
+    "syslog",
+    "wake_alarm",
+    "block_suspend",
+    "audit_read",
+    "perfmon",
+    "bpf",
+    "checkpoint_restore",
+];
+
+/// The capabilities a file grants to processes executing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// bit `n` is set if capability `n` is permitted
+    pub permitted: u64,
+    /// bit `n` is set if capability `n` is inheritable
+    pub inheritable: u64,
+    /// whether the permitted capabilities are effective straight away
+    pub effective: bool,
+    /// the uid owning the user namespace the capabilities apply in, for revision 3
+    pub root_id: Option<u32>,
+}
+
+impl Capabilities {
+    /// Decode the `security.capability` extended attribute: a little-endian word holding the
+    /// revision and flags, a permitted and inheritable word for each 32 capabilities, and
+    /// the root id for revision 3. Returns `None` if it is malformed or grants nothing.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let words = bytes
+            .chunks(4)
+            .map(|word| Some(u32::from_le_bytes(word.try_into().ok()?)))
+            .collect::<Option<Vec<_>>>()?;
+        let (&magic, rest) = words.split_first()?;
+        let (data, root_id) = match (magic & REVISION_MASK, rest) {
+            (REVISION_1, [p, i]) => ([*p, *i, 0, 0], None),
+            (REVISION_2, [p0, i0, p1, i1]) => ([*p0, *i0, *p1, *i1], None),
+            (REVISION_3, [p0, i0, p1, i1, root]) => ([*p0, *i0, *p1, *i1], Some(*root)),
+            _ => return None,
+        };
+        let caps = Self {
+            permitted: u64::from(data[0]) | u64::from(data[2]) << 32,
+            inheritable: u64::from(data[1]) | u64::from(data[3]) << 32,
+            effective: magic & FLAGS_EFFECTIVE != 0,
+            root_id,
+        };
+        (caps.permitted | caps.inheritable != 0).then_some(caps)
+    }
+
+    /// Read the capabilities of `path`, relative to the open directory `dir` if it is given, or
+    /// `None` if it has none or they cannot be read.
+    pub fn read(dir: Option<BorrowedFd<'_>>, path: &Path) -> Option<Self> {
+        Self::parse(&xattr::get(dir, path, OsStr::new(CAPS_XATTR)).ok()??)
+    }
+}
+
+/// Displays the capabilities like `getcap`, e.g. `cap_net_admin,cap_net_raw=ep`: capabilities
+/// with the same flags are grouped in the order of their numbers.
+impl Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let groups = (0..64usize)
+            .filter_map(|n| {
+                let bit = 1u64 << n;
+                let permitted = self.permitted & bit != 0;
+                let inheritable = self.inheritable & bit != 0;
+                if !permitted && !inheritable {
+                    return None;
+                }
+                let flags = [(self.effective, 'e'), (inheritable, 'i'), (permitted, 'p')]
+                    .iter()
+                    .filter_map(|&(set, flag)| set.then_some(flag))
+                    .collect::<String>();
+                Some((flags, n))
+            })
+            .into_group_map()
+            .into_iter()
+            .sorted_by_key(|(_, caps)| caps[0])
+            .map(|(flags, caps)| {
+                let names = caps.iter().map(|&n| match CAP_NAMES.get(n) {
+                    Some(name) => format!("cap_{name}"),
+                    None => format!("cap_{n}"),
+                });
+                format!("{}={flags}", names.format(","))
+            })
+            .join(" ");
+        write!(f, "{groups}")?;
+        match self.root_id {
+            Some(root_id) if root_id != 0 => write!(f, " [rootid={root_id}]"),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::display::test::{fixture, render};
+    use crate::lister::Lister;
+    use std::fs::File;
+    use std::os::fd::AsFd;
+
+    fn encode(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_parse_capabilities() {
+        let net_bind_service = 1 << 10;
+        let caps = Capabilities::parse(&encode(&[0x0200_0001, net_bind_service, 0, 0, 0]));
+        assert_eq!(caps.unwrap().to_string(), "cap_net_bind_service=ep");
+
+        // net_admin and net_raw share their flags, while bpf is only inheritable
+        let bpf = 1 << (39 - 32);
+        let words = [0x0300_0000, 0b11 << 12, 0, 0, bpf, 1000];
+        let caps = Capabilities::parse(&encode(&words)).unwrap();
+        assert_eq!(
+            caps.to_string(),
+            "cap_net_admin,cap_net_raw=p cap_bpf=i [rootid=1000]"
+        );
+
+        let caps = Capabilities::parse(&encode(&[0x0100_0001, 1, 1]));
+        assert_eq!(caps.unwrap().to_string(), "cap_chown=eip");
+        assert_eq!(
+            Capabilities::parse(&encode(&[0x0200_0001, 0, 0, 0, 0])),
+            None
+        );
+        assert_eq!(Capabilities::parse(&encode(&[0x0200_0001, 1, 0])), None);
+        assert_eq!(Capabilities::parse(&[1, 0, 0]), None);
+    }
+
+    #[test]
+    fn test_parse_xattr_blobs() {
+        // `security.capability` as written by `setcap cap_net_raw+ep`, then as written inside a
+        // user namespace owned by uid 1000
+        let v2 = b"\x01\x00\x00\x02\x00\x20\x00\x00\x00\x00\x00\x00\
+                   \x00\x00\x00\x00\x00\x00\x00\x00";
+        let caps = Capabilities::parse(v2).unwrap();
+        assert_eq!(caps.permitted, 1 << 13);
+        assert_eq!(caps.to_string(), "cap_net_raw=ep");
+        let v3 = b"\x01\x00\x00\x03\x00\x20\x00\x00\x00\x00\x00\x00\
+                   \x00\x00\x00\x00\x00\x00\x00\x00\xe8\x03\x00\x00";
+        let caps = Capabilities::parse(v3).unwrap();
+        assert_eq!(caps.root_id, Some(1000));
+        assert_eq!(caps.to_string(), "cap_net_raw=ep [rootid=1000]");
+        assert_eq!(Capabilities::parse(&v3[..v3.len() - 4]), None);
+    }
+
+    #[test]
+    fn test_read_capabilities() {
+        let tmp = fixture();
+        let caps = encode(&[0x0200_0001, 1 << 10, 0, 0, 0]);
+        // setting capabilities needs `CAP_SETFCAP`, so this can only run with it
+        match xattr::try_set(&tmp.path().join("run.sh"), CAPS_XATTR, &caps) {
+            Err(err) if err.raw_os_error() == Some(libc::EPERM) => return,
+            res => res.unwrap(),
+        }
+        let dir = File::open(tmp.path()).unwrap();
+        let read = Capabilities::read(Some(dir.as_fd()), Path::new("run.sh"));
+        assert_eq!(read, Capabilities::parse(&caps));
+        assert_eq!(
+            Capabilities::read(Some(dir.as_fd()), Path::new("alpha")),
+            None
+        );
+
+        let files = ["alpha", "run.sh"].map(|f| tmp.path().join(f));
+        let lister = Lister::new().paths(&files).one_per_line(true);
+        // capabilities are only read with `--caps`
+        assert_eq!(
+            render(lister.clone()),
+            "alpha\n\x1b[1m\x1b[38;5;2mrun.sh\x1b[m\n"
+        );
+        let out = render(lister.clone().caps(true));
+        assert_eq!(out, "alpha\n\x1b[38;5;0m\x1b[48;5;1mrun.sh\x1b[m\n");
+
+        let out = render(lister.long(true).color(false).caps(true));
+        let lines = out.lines().collect::<Vec<_>>();
+        assert!(
+            lines[0].contains(" -                       5 Jun 15 2020 alpha"),
+            "{out}"
+        );
+        assert!(
+            lines[1].contains(" cap_net_bind_service=ep 6 Jun 15 2020 run.sh"),
+            "{out}"
+        );
+    }
+}
@@ -0,0 +1,322 @@
+//! Default options for the `rusl` binary from a TOML config file and the `RUSL_OPTIONS`
+//! environment variable.
+//!
+//! The config file sets options as they would be given on the command line, either as a
+//! string separated by whitespace or as an array of strings:
+//! ```toml
+//! options = "--dirs-first --icons"
+//!
+//! [profiles.audit]
+//! options = ["-l", "--acl", "-@", "-Z"]
+//!
+//! [directories."~/src"]
+//! options = "--git"
+//! ```
+//!
+//! Only options can be set, not paths. Boolean options set here can be turned off again on the
+//! command line with `--no-` followed by their name, e.g. `--no-dirs-first`.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Environment variable holding default options, separated by whitespace.
+pub const OPTIONS_ENV: &str = "RUSL_OPTIONS";
+
+/// Errors encountered while loading the config file or picking options from it.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// the config file at `path` exists but could not be read
+    Read { path: PathBuf, err: io::Error },
+    /// the config file at `path` is not valid TOML, or does not have the expected keys
+    Parse { path: PathBuf, msg: String },
+    /// `--profile` names a profile the config file does not define
+    UnknownProfile(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read { path, err } => write!(f, "cannot read '{}': {err}", path.display()),
+            Self::Parse { path, msg } => write!(f, "invalid config '{}': {msg}", path.display()),
+            Self::UnknownProfile(name) => write!(f, "unknown profile '{name}'"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Read { err, .. } => Some(err),
+            Self::Parse { .. } | Self::UnknownProfile(_) => None,
+        }
+    }
+}
+
+/// Default options read from the config file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    /// the file the config was loaded from
+    path: Option<PathBuf>,
+    /// options used for every listing
+    options: Vec<String>,
+    /// options used with `--profile NAME`
+    profiles: HashMap<String, Vec<String>>,
+    /// options used when running inside a directory, ordered from the shallowest directory
+    directories: Vec<(PathBuf, Vec<String>)>,
+}
+
+impl Config {
+    /// `config.toml` in the [`config_dir`].
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    /// Load the config file at `path`. A missing file is the same as an empty one.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(ConfigError::Read {
+                    path: path.to_path_buf(),
+                    err,
+                });
+            }
+        };
+        let home = env::var_os("HOME").map(PathBuf::from);
+        let config = Self::parse(&text, home.as_deref()).map_err(|msg| ConfigError::Parse {
+            path: path.to_path_buf(),
+            msg,
+        })?;
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            ..config
+        })
+    }
+
+    /// Parse the contents of a config file, expanding a leading `~` in directories to `home`.
+    pub fn parse(text: &str, home: Option<&Path>) -> Result<Self, String> {
+        let table = text.parse::<Table>().map_err(|err| err.to_string())?;
+        let mut config = Self::default();
+        for (key, value) in &table {
+            match key.as_str() {
+                "options" => config.options = options(value, "options")?,
+                "profiles" => {
+                    for (name, profile) in sections(value, "profiles")? {
+                        let key = format!("profiles.{name}");
+                        config
+                            .profiles
+                            .insert(name.clone(), section_options(profile, &key)?);
+                    }
+                }
+                "directories" => {
+                    for (dir, section) in sections(value, "directories")? {
+                        let key = format!("directories.\"{dir}\"");
+                        let path = match (dir.strip_prefix("~"), home) {
+                            (Some(rest), Some(home)) => home.join(rest.trim_start_matches('/')),
+                            _ => PathBuf::from(dir),
+                        };
+                        // the working directory has its symlinks resolved, so these must too
+                        let path = path.canonicalize().unwrap_or(path);
+                        config
+                            .directories
+                            .push((path, section_options(section, &key)?));
+                    }
+                }
+                key => return Err(format!("unknown key `{key}`")),
+            }
+        }
+        config
+            .directories
+            .sort_by_key(|(path, _)| path.components().count());
+        Ok(config)
+    }
+
+    /// The default options for a listing run in `cwd`, in increasing order of precedence:
+    /// those set for every listing, those of each directory containing `cwd` from the
+    /// shallowest to the deepest, those in `env` from `RUSL_OPTIONS`, then those of `profile`.
+    /// Options given on the command line come after all of these, so they take precedence.
+    /// Sources which set no options are left out.
+    pub fn options(
+        &self,
+        cwd: &Path,
+        env: Option<&str>,
+        profile: Option<&str>,
+    ) -> Result<Vec<DefaultOptions>, ConfigError> {
+        let mut sources = vec![self.source("options", &self.options)];
+        for (dir, dir_options) in &self.directories {
+            if cwd.starts_with(dir) {
+                let key = format!("directories.\"{}\"", dir.display());
+                sources.push(self.source(&key, dir_options));
+            }
+        }
+        sources.push(DefaultOptions {
+            source: OPTIONS_ENV.to_string(),
+            options: env.map_or_else(Vec::new, split_options),
+        });
+        if let Some(name) = profile {
+            let profile = self
+                .profiles
+                .get(name)
+                .ok_or_else(|| ConfigError::UnknownProfile(name.to_string()))?;
+            sources.push(self.source(&format!("profiles.{name}"), profile));
+        }
+        sources.retain(|source| !source.options.is_empty());
+        Ok(sources)
+    }
+
+    /// The `options` set under `key` of the config file.
+    fn source(&self, key: &str, options: &[String]) -> DefaultOptions {
+        let file = self.path.as_ref().map_or_else(
+            || "config file".to_string(),
+            |path| path.display().to_string(),
+        );
+        DefaultOptions {
+            source: format!("{file} ({key})"),
+            options: options.to_vec(),
+        }
+    }
+}
+
+/// Default options and where they were set, to point to them in errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefaultOptions {
+    /// the config file and the key the options were set under, or `RUSL_OPTIONS`
+    pub source: String,
+    pub options: Vec<String>,
+}
+
+/// `$XDG_CONFIG_HOME/rusl`, with `XDG_CONFIG_HOME` defaulting to `~/.config`.
+pub fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|config| config.join("rusl"))
+}
+
+/// Split options given as a single string on whitespace.
+fn split_options(options: &str) -> Vec<String> {
+    options.split_whitespace().map(String::from).collect()
+}
+
+/// Options given as a string or as an array of strings for `key`.
+fn options(value: &Value, key: &str) -> Result<Vec<String>, String> {
+    let invalid = || format!("`{key}` must be a string or an array of strings");
+    match value {
+        Value::String(options) => Ok(split_options(options)),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_str().map(String::from).ok_or_else(invalid))
+            .collect(),
+        _ => Err(invalid()),
+    }
+}
+
+/// The named tables inside the table `key`.
+fn sections<'a>(value: &'a Value, key: &str) -> Result<&'a Table, String> {
+    value
+        .as_table()
+        .ok_or_else(|| format!("`{key}` must be a table"))
+}
+
+/// The options of the table `key`, which must not have any other keys.
+fn section_options(value: &Value, key: &str) -> Result<Vec<String>, String> {
+    let table = value
+        .as_table()
+        .ok_or_else(|| format!("`{key}` must be a table"))?;
+    if let Some(other) = table.keys().find(|k| *k != "options") {
+        return Err(format!("unknown key `{other}` in `{key}`"));
+    }
+    table.get("options").map_or(Ok(Vec::new()), |value| {
+        options(value, &format!("{key}.options"))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONFIG: &str = r#"
+options = "--dirs-first --icons=never"
+
+[profiles.audit]
+options = ["-l", "--acl"]
+
+[directories."~/src"]
+options = "--git"
+
+[directories."~/src/rusl"]
+options = ["--icons=always"]
+"#;
+
+    #[test]
+    fn test_options_precedence() {
+        let home = Path::new("/nonexistent/home");
+        let config = Config::parse(CONFIG, Some(home)).unwrap();
+        let options = |cwd: &str, env: Option<&str>, profile: Option<&str>| {
+            let sources = config.options(&home.join(cwd), env, profile).unwrap();
+            sources
+                .into_iter()
+                .flat_map(|source| source.options)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(options("", None, None), ["--dirs-first", "--icons=never"]);
+        assert_eq!(
+            options("src/rusl/src", Some(" -x  -S"), Some("audit")),
+            [
+                "--dirs-first",
+                "--icons=never",
+                "--git",
+                "--icons=always",
+                "-x",
+                "-S",
+                "-l",
+                "--acl",
+            ]
+        );
+        // each source is named so errors can point to it
+        let sources = config
+            .options(&home.join("src"), Some("-x"), Some("audit"))
+            .unwrap()
+            .into_iter()
+            .map(|source| source.source)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sources,
+            [
+                "config file (options)",
+                "config file (directories.\"/nonexistent/home/src\")",
+                "RUSL_OPTIONS",
+                "config file (profiles.audit)",
+            ]
+        );
+        // a directory only matches whole components
+        assert_eq!(options("srcs", None, None).len(), 2);
+        assert!(matches!(
+            config.options(home, None, Some("missing")),
+            Err(ConfigError::UnknownProfile(name)) if name == "missing"
+        ));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let parse = |text: &str| Config::parse(text, None).unwrap_err();
+        assert_eq!(parse("colour = true"), "unknown key `colour`");
+        assert_eq!(
+            parse("options = 1"),
+            "`options` must be a string or an array of strings"
+        );
+        assert_eq!(
+            parse("[profiles.a]\nopts = []"),
+            "unknown key `opts` in `profiles.a`"
+        );
+        assert!(!parse("options = [").is_empty());
+        assert_eq!(Config::parse("", None).unwrap(), Config::default());
+    }
+}
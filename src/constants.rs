@@ -3,3 +3,18 @@ pub const ERR_NO_SUCH_FILE_OR_DIR: &str = "No such file or directory";
 pub const ERR_PERM_DENIED: &str = "Permission denied";
 pub const MIN_COL_SIZE: usize = 3;
 pub const COL_SEP_LEN: usize = 2;
+/// width of the git status and the space after it
+pub const GIT_STATUS_LEN: usize = 3;
+/// width of an icon and the space after it, since Nerd Font glyphs may take up two cells
+pub const ICON_LEN: usize = 3;
+pub const TREE_BRANCH: &str = "├── ";
+pub const TREE_LAST_BRANCH: &str = "└── ";
+pub const TREE_PIPE: &str = "│   ";
+pub const TREE_BLANK: &str = "    ";
+pub const DEFAULT_TERM_COLS: usize = 80;
+pub const PARALLEL_STAT_MIN_ENTRIES: usize = 32;
+pub const PARALLEL_STAT_CHUNK_SIZE: usize = 16;
+pub const URING_BATCH_SIZE: usize = 256;
+pub const URING_MIN_ENTRIES: usize = 32;
+/// how long to wait for the terminal to report its background colour
+pub const BACKGROUND_QUERY_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(100);
@@ -0,0 +1,1118 @@
+use crate::acl::FileAcl;
+use crate::attrs::AttrFilter;
+use crate::constants::*;
+use crate::du::DuMode;
+use crate::filemode::{FileKind, FileMode};
+use crate::git::{GitFileStatus, GitStatus};
+use crate::hyperlink::file_url;
+use crate::icons::Icons;
+use crate::layout::{LayoutInfo, determine_layout};
+use crate::lister::{DirListing, Entry};
+use crate::pathinfo::{LongPathInfo, Meta, PathInfo, lstat, stat};
+use crate::theme::{Style, Theme};
+use itertools::Itertools;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Result as IOResult;
+use std::io::Write;
+use std::path::Path;
+use termion::color;
+use termion::style;
+
+/// Display options for formatting output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayOptions {
+    /// show hidden paths
+    pub all: bool,
+
+    /// use long listing format
+    pub long: bool,
+
+    /// display entries by lines instead of by columns
+    pub by_lines: bool,
+
+    /// list the contents of directories as a tree
+    pub tree: bool,
+
+    /// maximum depth to descend when listing as a tree
+    pub level: Option<usize>,
+
+    /// group directories before files
+    pub dirs_first: bool,
+
+    /// list entries in directory order instead of sorting them
+    pub unsorted: bool,
+
+    /// list one entry per line
+    pub one_per_line: bool,
+
+    /// colour entries according to their file type
+    pub color: bool,
+
+    /// styles used with `color` for entry names and the fields of the long format
+    pub theme: Theme,
+
+    /// replace the size of directories with the recursive size of their contents
+    pub du: Option<DuMode>,
+
+    /// do not count directories on other filesystems with `du`
+    pub one_file_system: bool,
+
+    /// sort by size, largest first
+    pub sort_size: bool,
+
+    /// show the git status of entries inside a work tree
+    pub git: bool,
+
+    /// hide entries ignored by git
+    pub git_ignore: bool,
+
+    /// hide entries matched by `.gitignore`, `.ignore` and git's exclude files
+    pub ignore_vcs: bool,
+
+    /// glyphs shown before each entry, if any
+    pub icons: Option<Icons>,
+
+    /// list the extended attributes of entries below each long row
+    pub xattrs: bool,
+
+    /// show the values of extended attributes instead of their sizes
+    pub xattr_values: bool,
+
+    /// list the ACL entries of entries below each long row
+    pub acl: bool,
+
+    /// show the SELinux security context of entries
+    pub context: bool,
+
+    /// show the file capabilities of entries in the long format, and colour entries which
+    /// have any
+    pub caps: bool,
+
+    /// show the inode flags of entries in the long format
+    pub attrs: bool,
+
+    /// hide entries whose inode flags do not match
+    pub attr_filter: Option<AttrFilter>,
+
+    /// show every symlink a symlink target goes through
+    pub link_chain: bool,
+
+    /// link the names of entries to their files with OSC 8 escape sequences
+    pub hyperlink: bool,
+}
+
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        Self {
+            all: false,
+            long: false,
+            by_lines: false,
+            tree: false,
+            level: None,
+            dirs_first: false,
+            unsorted: false,
+            one_per_line: false,
+            color: true,
+            theme: Theme::default(),
+            du: None,
+            one_file_system: false,
+            sort_size: false,
+            git: false,
+            git_ignore: false,
+            ignore_vcs: false,
+            icons: None,
+            xattrs: false,
+            xattr_values: false,
+            acl: false,
+            context: false,
+            caps: false,
+            attrs: false,
+            attr_filter: None,
+            link_chain: false,
+            hyperlink: false,
+        }
+    }
+}
+
+/// Column widths needed to align the fields of a set of `LongPathInfo`s.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct LongColumnWidths {
+    filetype_mode: usize,
+    num_links: usize,
+    file_owner: usize,
+    file_group: usize,
+    security_context: usize,
+    capabilities: usize,
+    inode_flags: usize,
+    size: usize,
+    last_modified: usize,
+}
+
+impl LongColumnWidths {
+    /// Compute the widest value of each field across `longpaths`.
+    fn new<'a>(longpaths: impl IntoIterator<Item = &'a LongPathInfo>) -> Self {
+        longpaths.into_iter().fold(Self::default(), |acc, p| Self {
+            filetype_mode: std::cmp::max(acc.filetype_mode, p.filetype_mode.len()),
+            num_links: std::cmp::max(acc.num_links, p.num_links.len()),
+            file_owner: std::cmp::max(acc.file_owner, p.file_owner.len()),
+            file_group: std::cmp::max(acc.file_group, p.file_group.len()),
+            security_context: std::cmp::max(acc.security_context, p.security_context.len()),
+            capabilities: std::cmp::max(acc.capabilities, p.capabilities.len()),
+            inode_flags: std::cmp::max(acc.inode_flags, p.inode_flags.len()),
+            size: std::cmp::max(acc.size, p.size.len()),
+            last_modified: std::cmp::max(acc.last_modified, p.last_modified.len()),
+        })
+    }
+}
+
+/// Write `value` in `style` if `opts.color` is set, followed by the spaces filling `width`
+/// characters and the space separating it from the next field.
+fn write_field(
+    w: &mut impl Write,
+    opts: &DisplayOptions,
+    style: Style,
+    value: &str,
+    width: usize,
+) -> IOResult<()> {
+    if opts.color {
+        style.write(w, value)?;
+    } else {
+        write!(w, "{value}")?;
+    }
+    let indent_len = width.saturating_sub(value.chars().count());
+    write!(w, "{} ", " ".repeat(indent_len))
+}
+
+/// Write the file type and mode `s`, styling each permission character with `opts.theme` if
+/// `opts.color` is set, followed by the spaces filling `width` characters.
+fn write_mode(w: &mut impl Write, opts: &DisplayOptions, s: &str, width: usize) -> IOResult<()> {
+    if !opts.color {
+        return write!(w, "{s:width$} ");
+    }
+    let mut chars = s.chars();
+    // the first character is the file type, which is not a permission
+    if let Some(c) = chars.next() {
+        write!(w, "{c}")?;
+    }
+    let mut buf = [0; 4];
+    for c in chars {
+        opts.theme.perm(c).write(w, c.encode_utf8(&mut buf))?;
+    }
+    let indent_len = width.saturating_sub(s.chars().count());
+    write!(w, "{} ", " ".repeat(indent_len))
+}
+
+/// Write every field of `p` preceding the file name, aligned using `widths`. The security
+/// context is only written with `opts.context`, the capabilities with `opts.caps` and the inode
+/// flags with `opts.attrs`.
+fn write_long_fields(
+    w: &mut impl Write,
+    opts: &DisplayOptions,
+    widths: &LongColumnWidths,
+    p: &LongPathInfo,
+) -> IOResult<()> {
+    let theme = &opts.theme;
+    write_mode(w, opts, &p.filetype_mode, widths.filetype_mode)?;
+    write!(w, "{:width$} ", p.num_links, width = widths.num_links)?;
+    write_field(w, opts, theme.user, &p.file_owner, widths.file_owner)?;
+    write_field(w, opts, theme.group, &p.file_group, widths.file_group)?;
+    if opts.context {
+        write!(
+            w,
+            "{:width$} ",
+            p.security_context,
+            width = widths.security_context
+        )?;
+    }
+    if opts.caps {
+        write!(w, "{:width$} ", p.capabilities, width = widths.capabilities)?;
+    }
+    if opts.attrs {
+        write!(w, "{:width$} ", p.inode_flags, width = widths.inode_flags)?;
+    }
+    write_field(w, opts, theme.size, &p.size, widths.size)?;
+    write_field(w, opts, theme.date, &p.last_modified, widths.last_modified)
+}
+
+/// Write `target` coloured by the type of the file at `resolved` it leads to, whose metadata is
+/// `meta`, or in the style of dangling targets if it does not lead to anything.
+fn write_target(
+    w: &mut impl Write,
+    opts: &DisplayOptions,
+    target: &Path,
+    resolved: &Path,
+    meta: Option<&Meta>,
+) -> IOResult<()> {
+    let s = target.display().to_string();
+    match meta {
+        Some(meta) => {
+            let kind = meta.kind();
+            let executable = opts.color && kind.is_file() && is_executable(meta);
+            write_name(w, opts, &s, kind, executable, false, Some(resolved))
+        }
+        None if opts.color => opts.theme.orphan().write(w, &s),
+        None => write!(w, "{s}"),
+    }
+}
+
+/// Write ` -> target` if `p` is a symlink, with the target as it was written rather than
+/// resolved. With `opts.link_chain`, each further symlink the target goes through is followed
+/// by its own ` -> target`, stopping at a loop, which is marked with `[loop]`.
+fn write_link_target(w: &mut impl Write, opts: &DisplayOptions, p: &PathInfo) -> IOResult<()> {
+    if !p.is_symlink() {
+        return Ok(());
+    }
+    let mut link = p.path.clone();
+    let mut seen = HashSet::new();
+    if let Some(meta) = p.meta() {
+        seen.insert((meta.dev, meta.ino));
+    }
+    loop {
+        write!(w, " -> ")?;
+        let Ok(target) = fs::read_link(&link) else {
+            return write_target(w, opts, Path::new("?"), &link, None);
+        };
+        // relative targets are resolved from the directory containing the link
+        let next = link.parent().unwrap_or(Path::new("")).join(&target);
+        if !opts.link_chain {
+            return write_target(w, opts, &target, &next, stat(&next).ok().as_ref());
+        }
+        match lstat(&next) {
+            Ok(meta) if meta.kind().is_symlink() => {
+                write_target(w, opts, &target, &next, Some(&meta))?;
+                if !seen.insert((meta.dev, meta.ino)) {
+                    return write!(w, " [loop]");
+                }
+                link = next;
+            }
+            Ok(meta) => return write_target(w, opts, &target, &next, Some(&meta)),
+            Err(_) => return write_target(w, opts, &target, &next, None),
+        }
+    }
+}
+
+/// Mark `p` after its permissions with `+` if it has an ACL, and with `@` if it has extended
+/// attributes and they are shown with `-@`. Returns the lines shown below its row: its extended
+/// attributes with `-@`, with their sizes or with `--xattr-values` their values, followed by its
+/// ACL entries with `--acl`. Attributes and ACLs which cannot be read are not shown.
+fn long_details(opts: &DisplayOptions, p: &mut LongPathInfo) -> Vec<String> {
+    let mut details = Vec::new();
+    if p.path.acl().is_some_and(FileAcl::is_present) {
+        p.filetype_mode.push('+');
+    }
+    if opts.xattrs {
+        let xattrs = p.path.xattrs();
+        if !xattrs.is_empty() {
+            p.filetype_mode.push('@');
+        }
+        let width = xattrs.iter().map(|x| x.name.len()).max().unwrap_or(0);
+        details.extend(xattrs.iter().map(|x| {
+            let value = if opts.xattr_values {
+                x.display_value()
+            } else {
+                x.value.len().to_string()
+            };
+            format!("{:width$}  {value}", x.name.to_string_lossy())
+        }));
+    }
+    if opts.acl
+        && let Some(acl) = p.path.acl()
+    {
+        details.extend(acl.lines());
+    }
+    details
+}
+
+/// Write each of `details` on its own indented line below a long row.
+fn write_long_details(w: &mut impl Write, details: &[String]) -> IOResult<()> {
+    for line in details {
+        writeln!(w, "    {line}")?;
+    }
+    Ok(())
+}
+
+/// Display `paths` using the long format for ls. The structure for the format is
+/// ```text
+/// filetype_and_mode number_of_links file_owner file_group file_size last_modified file_name
+/// ```
+fn display_pathinfo_long(
+    w: &mut impl Write,
+    opts: &DisplayOptions,
+    paths: &[&PathInfo],
+) -> IOResult<()> {
+    let mut longpaths = paths
+        .iter()
+        .map(|&p| LongPathInfo::from(p.clone()))
+        .collect_vec();
+    let details = longpaths
+        .iter_mut()
+        .map(|p| long_details(opts, p))
+        .collect_vec();
+    let widths = LongColumnWidths::new(&longpaths);
+    for (p, details) in longpaths.iter().zip(&details) {
+        // print all the fields with width and alignment
+        write_long_fields(w, opts, &widths, p)?;
+        // file_name
+        write_pathinfo(w, opts, &p.path, 0)?;
+        // optionally print link info
+        write_link_target(w, opts, &p.path)?;
+        writeln!(w)?;
+        write_long_details(w, details)?;
+    }
+    Ok(())
+}
+
+/// Determine a layout for the `paths` based on `term_cols` and display them
+pub(crate) fn display_paths(
+    w: &mut impl Write,
+    opts: &DisplayOptions,
+    term_cols: usize,
+    paths: &[&PathInfo],
+) -> IOResult<()> {
+    if opts.long {
+        return display_pathinfo_long(w, opts, paths);
+    } else if opts.one_per_line {
+        return display_one_per_line(w, opts, paths);
+    }
+    let lens = paths
+        .iter()
+        // names are measured in characters, like GNU `ls` does for printable names
+        .map(|p| prefix_len(opts, p) + p.to_string().chars().count())
+        .collect_vec();
+    let layout = determine_layout(opts.by_lines, term_cols, &lens);
+    if opts.by_lines {
+        display_by_lines(w, opts, &layout, paths)
+    } else {
+        display_by_cols(w, opts, &layout, paths)
+    }
+}
+
+/// Display `paths` ascending down columns using the number of columns and
+/// column widths specified by `layout`. Like GNU `ls`, each column holds
+/// `paths.len().div_ceil(layout.num_cols)` rows, so the last columns may be shorter or empty.
+fn display_by_cols(
+    w: &mut impl Write,
+    opts: &DisplayOptions,
+    layout: &LayoutInfo,
+    paths: &[&PathInfo],
+) -> IOResult<()> {
+    let num_rows = paths.len().div_ceil(layout.num_cols);
+    for r in 0..num_rows {
+        let row = paths[r..].iter().step_by(num_rows).copied().collect_vec();
+        write_row(w, opts, layout, &row)?;
+    }
+    Ok(())
+}
+
+/// Write the entries of one row of a grid, padding each but the last to the width of its
+/// column in `layout`.
+fn write_row(
+    w: &mut impl Write,
+    opts: &DisplayOptions,
+    layout: &LayoutInfo,
+    row: &[&PathInfo],
+) -> IOResult<()> {
+    let last = row.len().saturating_sub(1);
+    for (ind, p) in row.iter().enumerate() {
+        let col_width = if ind == last {
+            0
+        } else {
+            layout.col_width[ind]
+        };
+        write_pathinfo(w, opts, p, col_width)?;
+    }
+    writeln!(w)
+}
+
+/// Check if `meta` represents an executable file (i.e., any execute bit set)
+fn is_executable(meta: &Meta) -> bool {
+    let mode = FileMode(meta.mode);
+    mode.user_execute() || mode.group_execute() || mode.other_execute()
+}
+
+/// Check if displaying `path` using `opts` needs its metadata. Only regular files are coloured
+/// and given icons by their mode, so their file type is enough otherwise.
+pub(crate) fn needs_meta(opts: &DisplayOptions, path: &PathInfo) -> bool {
+    opts.long
+        || opts.du.is_some()
+        || (opts.sort_size && !opts.unsorted)
+        || ((opts.color || opts.icons.is_some()) && path.file_type().is_file())
+}
+
+/// The security context written before the name of `path` with `opts.context`, which is a
+/// column of its own in the long format. Shows `?` for paths without one.
+fn context_prefix<'a>(opts: &DisplayOptions, path: &'a PathInfo) -> Option<&'a str> {
+    (opts.context && !opts.long).then(|| path.security_context().unwrap_or("?"))
+}
+
+/// The width of what is written before the name of `path`: its security context and the space
+/// after it with `opts.context`, its git status with `opts.git` for paths inside a work tree,
+/// and its icon with `opts.icons`.
+fn prefix_len(opts: &DisplayOptions, path: &PathInfo) -> usize {
+    let context_len = context_prefix(opts, path).map_or(0, |context| context.len() + 1);
+    let git_status_len = if opts.git && path.git_status().is_some() {
+        GIT_STATUS_LEN
+    } else {
+        0
+    };
+    let icon_len = if opts.icons.is_some() { ICON_LEN } else { 0 };
+    context_len + git_status_len + icon_len
+}
+
+/// Write the staged and unstaged git status of an entry followed by a space, coloured like
+/// `git status --short`.
+fn write_git_status(
+    w: &mut impl Write,
+    opts: &DisplayOptions,
+    status: GitFileStatus,
+) -> IOResult<()> {
+    for s in [status.staged, status.unstaged] {
+        let fg: &dyn color::Color = match s {
+            GitStatus::Unmodified => {
+                write!(w, "{}", s.symbol())?;
+                continue;
+            }
+            GitStatus::New => &color::Green,
+            GitStatus::Modified => &color::Blue,
+            GitStatus::Deleted | GitStatus::Conflicted => &color::Red,
+            GitStatus::Renamed => &color::Yellow,
+            GitStatus::TypeChange => &color::Magenta,
+            GitStatus::Ignored => &color::LightBlack,
+        };
+        if opts.color {
+            write!(w, "{}{}{}", color::Fg(fg), s.symbol(), style::Reset)?;
+        } else {
+            write!(w, "{}", s.symbol())?;
+        }
+    }
+    write!(w, " ")
+}
+
+/// Write `path` using ls-like colors according to the file type, preceded by its security
+/// context with `opts.context`, its git status with `opts.git` and its icon with `opts.icons`.
+/// Add whitespace after the path to fill `col_width` characters.
+pub(crate) fn write_pathinfo(
+    w: &mut impl Write,
+    opts: &DisplayOptions,
+    path: &PathInfo,
+    col_width: usize,
+) -> IOResult<()> {
+    if let Some(context) = context_prefix(opts, path) {
+        write!(w, "{context} ")?;
+    }
+    if let Some(status) = path.git_status().filter(|_| opts.git) {
+        write_git_status(w, opts, status)?;
+    }
+    // only regular files are coloured and given icons by their mode, so avoid fetching metadata
+    // otherwise
+    let executable = (opts.color || opts.icons.is_some())
+        && path.file_type().is_file()
+        && path.meta().is_some_and(is_executable);
+    if let Some(icons) = &opts.icons {
+        let name = path.path.file_name().unwrap_or_default().to_string_lossy();
+        write!(w, "{} ", icons.icon(&name, path.file_type(), executable))?;
+    }
+    let executable = executable && opts.color;
+    let s = path.to_string();
+    let capable = path.capabilities().is_some();
+    write_name(
+        w,
+        opts,
+        &s,
+        path.file_type(),
+        executable,
+        capable,
+        Some(&path.path),
+    )?;
+    // when there is only 1 column, it is possible that the width does not accomodate the
+    // the string
+    let col_width = col_width.saturating_sub(prefix_len(opts, path));
+    let indent_len = col_width.saturating_sub(s.chars().count());
+    write!(w, "{}", " ".repeat(indent_len))
+}
+
+/// Write the name `s` of an entry in the style `opts.theme` gives to `file_type`, taking into
+/// account whether the entry is `executable` or `capable`, i.e. has file capabilities. With
+/// `opts.hyperlink`, the name links to the file at `link`.
+fn write_name(
+    w: &mut impl Write,
+    opts: &DisplayOptions,
+    s: &str,
+    file_type: FileKind,
+    executable: bool,
+    capable: bool,
+    link: Option<&Path>,
+) -> IOResult<()> {
+    let url = link.filter(|_| opts.hyperlink).and_then(file_url);
+    if let Some(url) = &url {
+        write!(w, "\x1b]8;;{url}\x1b\\")?;
+    }
+    if opts.color {
+        opts.theme
+            .name(file_type, executable, capable)
+            .write(w, s)?;
+    } else {
+        write!(w, "{s}")?;
+    }
+    if url.is_some() {
+        write!(w, "\x1b]8;;\x1b\\")?;
+    }
+    Ok(())
+}
+
+/// Display `paths` with each on its own line and no padding.
+fn display_one_per_line(
+    w: &mut impl Write,
+    opts: &DisplayOptions,
+    paths: &[&PathInfo],
+) -> IOResult<()> {
+    for p in paths {
+        write_pathinfo(w, opts, p, 0)?;
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// Display `paths` ascending across rows using the number of columns and
+/// column widths specified by `layout`
+fn display_by_lines(
+    w: &mut impl Write,
+    opts: &DisplayOptions,
+    layout: &LayoutInfo,
+    paths: &[&PathInfo],
+) -> IOResult<()> {
+    for row in paths.chunks(layout.num_cols) {
+        write_row(w, opts, layout, row)?;
+    }
+    Ok(())
+}
+
+/// Print the entries collected for `dir`.
+/// Optionally, include a total size if `opts.long` is `true`.
+fn display_dir_contents(
+    w: &mut impl Write,
+    opts: &DisplayOptions,
+    term_cols: usize,
+    dir: &DirListing,
+) -> IOResult<()> {
+    if opts.long {
+        let size = dir.dir.meta().map(|meta| meta.size).unwrap_or_default();
+        writeln!(w, "total {size}")?;
+    }
+    let children = dir.entries.iter().map(|e| &e.info).collect_vec();
+    display_paths(w, opts, term_cols, &children)
+}
+
+/// Iterate over all directories in `dirs`, displaying each.
+/// If there is more than one directory, preface the directory contents with
+/// the directory name.
+pub(crate) fn display_dirs(
+    w: &mut impl Write,
+    opts: &DisplayOptions,
+    term_cols: usize,
+    dirs: &[DirListing],
+) -> IOResult<()> {
+    // print files from dirs grouped if there are more than one
+    if dirs.len() > 1 {
+        writeln!(w, "{}:", dirs[0].dir.path.display())?;
+    }
+    for (ind, dir) in dirs[..dirs.len() - 1].iter().enumerate() {
+        display_dir_contents(w, opts, term_cols, dir)?;
+        writeln!(w)?;
+        writeln!(w, "{}:", dirs[ind + 1].dir.path.display())?;
+    }
+    // print the final dir
+    display_dir_contents(w, opts, term_cols, &dirs[dirs.len() - 1])
+}
+
+/// Flatten the nested `entries` into lines of tree output, pairing each entry with the branches
+/// drawn before it. `indent` holds the branches inherited from the ancestors of `entries`.
+fn tree_rows<'a>(entries: &'a [Entry], indent: &str, rows: &mut Vec<(String, &'a PathInfo)>) {
+    let last = entries.len().saturating_sub(1);
+    for (ind, entry) in entries.iter().enumerate() {
+        let (branch, continuation) = if ind == last {
+            (TREE_LAST_BRANCH, TREE_BLANK)
+        } else {
+            (TREE_BRANCH, TREE_PIPE)
+        };
+        rows.push((format!("{indent}{branch}"), &entry.info));
+        tree_rows(&entry.children, &format!("{indent}{continuation}"), rows);
+    }
+}
+
+/// Display `dir` and its descendants as a tree, followed by the number of directories and
+/// files shown. If `opts.long` is `true`, the long format fields are printed to the left of
+/// the branches.
+fn display_tree(w: &mut impl Write, opts: &DisplayOptions, dir: &DirListing) -> IOResult<()> {
+    let mut rows = Vec::new();
+    tree_rows(&dir.entries, "", &mut rows);
+
+    // the root is printed as it was given rather than by its final component
+    let root = dir.dir.path.display().to_string();
+    write_name(
+        w,
+        opts,
+        &root,
+        dir.dir.file_type(),
+        false,
+        false,
+        Some(&dir.dir.path),
+    )?;
+    writeln!(w)?;
+    if opts.long {
+        let mut longrows = rows
+            .iter()
+            .map(|(branches, p)| (branches, LongPathInfo::from((*p).clone())))
+            .collect_vec();
+        let details = longrows
+            .iter_mut()
+            .map(|(_, p)| long_details(opts, p))
+            .collect_vec();
+        let widths = LongColumnWidths::new(longrows.iter().map(|(_, p)| p));
+        for ((branches, p), details) in longrows.iter().zip(&details) {
+            write_long_fields(w, opts, &widths, p)?;
+            write!(w, "{branches}")?;
+            write_pathinfo(w, opts, &p.path, 0)?;
+            write_link_target(w, opts, &p.path)?;
+            writeln!(w)?;
+            write_long_details(w, details)?;
+        }
+    } else {
+        for (branches, p) in &rows {
+            write!(w, "{branches}")?;
+            write_pathinfo(w, opts, p, 0)?;
+            writeln!(w)?;
+        }
+    }
+
+    let num_dirs = rows.iter().filter(|(_, p)| p.is_dir()).count();
+    let num_files = rows.len() - num_dirs;
+    let plural =
+        |n: usize, one: &str, many: &str| format!("{n} {}", if n == 1 { one } else { many });
+    writeln!(w)?;
+    writeln!(
+        w,
+        "{}, {}",
+        plural(num_dirs, "directory", "directories"),
+        plural(num_files, "file", "files")
+    )
+}
+
+/// Display each directory in `dirs` as a tree, separated by blank lines.
+pub(crate) fn display_trees(
+    w: &mut impl Write,
+    opts: &DisplayOptions,
+    dirs: &[DirListing],
+) -> IOResult<()> {
+    for (ind, dir) in dirs.iter().enumerate() {
+        if ind > 0 {
+            writeln!(w)?;
+        }
+        display_tree(w, opts, dir)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::lister::Lister;
+    use nix::unistd::{Gid, Group, Uid, User};
+    use std::fs;
+    use std::fs::File;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt, symlink};
+    use std::time::{Duration, SystemTime};
+    use tempfile::TempDir;
+
+    /// Jun 15 2020 12:00 UTC, old enough that only the date is shown and on the same day in
+    /// every timezone.
+    const FIXTURE_MTIME: u64 = 1_592_222_400;
+
+    /// Create a directory containing:
+    /// ```text
+    /// .hidden
+    /// alpha
+    /// beta.txt
+    /// docs/
+    ///   guide.md
+    ///   img/
+    ///     logo.png
+    /// link -> alpha
+    /// run.sh (executable)
+    /// zeta
+    /// ```
+    pub(crate) fn fixture() -> TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("docs/img")).unwrap();
+        for (name, mode) in [
+            (".hidden", 0o644),
+            ("alpha", 0o644),
+            ("beta.txt", 0o644),
+            ("run.sh", 0o755),
+            ("zeta", 0o600),
+            ("docs/guide.md", 0o644),
+            ("docs/img/logo.png", 0o644),
+        ] {
+            let path = root.join(name);
+            fs::write(&path, name).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+            let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(FIXTURE_MTIME);
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(mtime)
+                .unwrap();
+        }
+        symlink("alpha", root.join("link")).unwrap();
+        tmp
+    }
+
+    pub(crate) fn render(lister: Lister) -> String {
+        let mut out = Vec::new();
+        let errors = lister.render(&mut out).unwrap();
+        assert!(errors.is_empty(), "{errors:?}");
+        String::from_utf8(out).unwrap()
+    }
+
+    fn dir(s: &str) -> String {
+        format!("\x1b[1m\x1b[38;5;4m{s}\x1b[m")
+    }
+
+    fn link(s: &str) -> String {
+        format!("\x1b[1m\x1b[38;5;6m{s}\x1b[m")
+    }
+
+    fn exec(s: &str) -> String {
+        format!("\x1b[1m\x1b[38;5;2m{s}\x1b[m")
+    }
+
+    #[test]
+    fn test_display_by_cols() {
+        let tmp = fixture();
+        let out = render(Lister::new().path(tmp.path()).term_cols(30));
+        let expected = format!(
+            "alpha     {}  {}\nbeta.txt  {}   zeta\n",
+            dir("docs/"),
+            exec("run.sh"),
+            link("link"),
+        );
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_display_by_lines() {
+        let tmp = fixture();
+        let out = render(Lister::new().path(tmp.path()).term_cols(30).by_lines(true));
+        let expected = format!(
+            "alpha   beta.txt  {}  {}\n{}  zeta\n",
+            dir("docs/"),
+            link("link"),
+            exec("run.sh"),
+        );
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_display_single_column() {
+        let tmp = fixture();
+        let out = render(Lister::new().path(tmp.path()).term_cols(5).all(true));
+        let expected = format!(
+            ".hidden\nalpha\nbeta.txt\n{}\n{}\n{}\nzeta\n",
+            dir("docs/"),
+            link("link"),
+            exec("run.sh"),
+        );
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_display_long() {
+        let tmp = fixture();
+        let meta = fs::metadata(tmp.path().join("alpha")).unwrap();
+        let owner = User::from_uid(Uid::from_raw(meta.uid()))
+            .unwrap()
+            .map(|u| u.name)
+            .unwrap_or_default();
+        let group = Group::from_gid(Gid::from_raw(meta.gid()))
+            .unwrap()
+            .map(|g| g.name)
+            .unwrap_or_default();
+        let files = ["alpha", "run.sh", "beta.txt"].map(|f| tmp.path().join(f));
+        let out = render(Lister::new().paths(&files).long(true));
+        let row = |mode: &str, size: usize, name: &str| {
+            format!("{mode} 1 {owner} {group} {size} Jun 15 2020 {name}\n")
+        };
+        let expected = [
+            row("-rw-r--r--", 5, "alpha"),
+            row("-rw-r--r--", 8, "beta.txt"),
+            row("-rwxr-xr-x", 6, &exec("run.sh")),
+        ]
+        .concat();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_theme() {
+        use crate::theme::{ColorDepth, Theme};
+        let tmp = fixture();
+        let theme = Theme::parse(
+            r##"
+size = { fg = "#ff8700" }
+user = { underline = true }
+
+[types]
+di = { fg = "#268bd2", italic = true }
+
+[perms]
+exec = { fg = 2 }
+"##,
+            None,
+        )
+        .unwrap();
+        let lister = Lister::new().path(tmp.path()).long(true);
+        let out = render(lister.clone().theme(theme.clone()));
+        let strip = |out: &str| {
+            let mut stripped = String::new();
+            for (ind, part) in out.split('\x1b').enumerate() {
+                // every escape sequence used is a CSI ending in `m`
+                let text = if ind == 0 {
+                    part
+                } else {
+                    part.split_once('m').unwrap().1
+                };
+                stripped.push_str(text);
+            }
+            stripped
+        };
+        assert_eq!(strip(&out), render(lister.clone().color(false)));
+        assert!(
+            out.contains("\x1b[3m\x1b[38;2;38;139;210mdocs/\x1b[m"),
+            "{out}"
+        );
+        assert!(out.contains("\x1b[38;2;255;135;0m6\x1b[m "), "{out}");
+        // only the execute bits are styled
+        assert!(
+            out.contains("-rw\x1b[38;5;2mx\x1b[mr-\x1b[38;5;2mx\x1b[mr-\x1b[38;5;2mx\x1b[m "),
+            "{out}"
+        );
+        // without true colour the closest colour of the 256 colour palette is used
+        let out = render(lister.theme(theme.downgrade(ColorDepth::Fixed)));
+        assert!(out.contains("\x1b[38;5;208m6\x1b[m "), "{out}");
+    }
+
+    #[test]
+    fn test_link_targets() {
+        let tmp = fixture();
+        let links = tmp.path().join("links");
+        fs::create_dir(&links).unwrap();
+        for (link, target) in [
+            ("broken", "missing"),
+            ("chain", "hop"),
+            ("hop", "../alpha"),
+            ("loop_a", "loop_b"),
+            ("loop_b", "loop_a"),
+        ] {
+            symlink(target, links.join(link)).unwrap();
+        }
+        let lister = Lister::new().path(&links).long(true);
+        let targets = |lister: Lister| {
+            render(lister)
+                .lines()
+                .skip(1)
+                .map(|line| line.split_once(" -> ").unwrap().1.to_string())
+                .collect::<Vec<_>>()
+        };
+        // targets are shown as written, coloured by what they resolve to
+        let red = |s: &str| format!("\x1b[1m\x1b[38;5;1m{s}\x1b[m");
+        assert_eq!(
+            targets(lister.clone()),
+            [
+                red("missing"),
+                "hop".to_string(),
+                "../alpha".to_string(),
+                red("loop_b"),
+                red("loop_a"),
+            ]
+        );
+        assert_eq!(
+            targets(lister.color(false).link_chain(true)),
+            [
+                "missing",
+                "hop -> ../alpha",
+                "../alpha",
+                "loop_b -> loop_a [loop]",
+                "loop_a -> loop_b [loop]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_display_tree() {
+        let tmp = fixture();
+        let out = render(Lister::new().path(tmp.path()).tree(true));
+        let expected = format!(
+            concat!(
+                "{}\n",
+                "├── alpha\n",
+                "├── beta.txt\n",
+                "├── {}\n",
+                "│   ├── guide.md\n",
+                "│   └── {}\n",
+                "│       └── logo.png\n",
+                "├── {}\n",
+                "├── {}\n",
+                "└── zeta\n",
+                "\n",
+                "2 directories, 7 files\n",
+            ),
+            dir(&tmp.path().display().to_string()),
+            dir("docs/"),
+            dir("img/"),
+            link("link"),
+            exec("run.sh"),
+        );
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_display_tree_level_dirs_first() {
+        let tmp = fixture();
+        let out = render(
+            Lister::new()
+                .path(tmp.path())
+                .tree(true)
+                .level(Some(1))
+                .dirs_first(true),
+        );
+        let expected = format!(
+            concat!(
+                "{}\n",
+                "├── {}\n",
+                "├── alpha\n",
+                "├── beta.txt\n",
+                "├── {}\n",
+                "├── {}\n",
+                "└── zeta\n",
+                "\n",
+                "1 directory, 5 files\n",
+            ),
+            dir(&tmp.path().display().to_string()),
+            dir("docs/"),
+            link("link"),
+            exec("run.sh"),
+        );
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_display_multiple_dirs() {
+        let tmp = fixture();
+        let docs = tmp.path().join("docs");
+        let img = docs.join("img");
+        let out = render(Lister::new().paths([&img, &docs]));
+        let expected = format!(
+            "{}:\nguide.md  {}\n\n{}:\nlogo.png\n",
+            docs.display(),
+            dir("img/"),
+            img.display(),
+        );
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_stream_matches_write() {
+        let tmp = fixture();
+        let docs = tmp.path().join("docs");
+        let lister = Lister::new()
+            .paths([tmp.path(), &docs])
+            .path(tmp.path().join("alpha"))
+            .unsorted(true)
+            .one_per_line(true);
+        assert!(lister.streams());
+        let mut written = Vec::new();
+        lister.write(&lister.list(), &mut written).unwrap();
+        let streamed = render(lister.clone());
+        assert_eq!(streamed, String::from_utf8(written).unwrap());
+
+        let out = render(lister.all(true).color(false));
+        assert!(out.starts_with("alpha\n"));
+        assert!(out.contains("\n.hidden\n"));
+        assert!(out.contains("\ndocs/\n"));
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn test_stat_calls() {
+        use crate::pathinfo::stat_calls;
+        let tmp = fixture();
+        let count = |lister: Lister| {
+            let before = stat_calls();
+            render(lister);
+            stat_calls() - before
+        };
+        // without colours only the operand is stat-ed
+        assert_eq!(count(Lister::new().path(tmp.path()).color(false)), 1);
+        let streamed = Lister::new()
+            .path(tmp.path())
+            .unsorted(true)
+            .one_per_line(true)
+            .color(false);
+        assert_eq!(count(streamed), 1);
+        // regular files are stat-ed to check if they are executable
+        assert_eq!(count(Lister::new().path(tmp.path())), 5);
+        assert_eq!(count(Lister::new().path(tmp.path()).tree(true)), 7);
+        // the long format needs metadata for every entry, and for the target of the symlink to
+        // colour it
+        assert_eq!(count(Lister::new().path(tmp.path()).long(true)), 8);
+    }
+
+    #[test]
+    fn test_tree_beyond_path_max() {
+        use nix::fcntl::{OFlag, open, openat};
+        use nix::sys::stat::{Mode, mkdirat};
+        let tmp = tempfile::tempdir().unwrap();
+        let name = "d".repeat(200);
+        // build the chain relative to each parent since the full path is too long to create
+        let flags = OFlag::O_RDONLY | OFlag::O_DIRECTORY;
+        let mut fd = open(tmp.path(), flags, Mode::empty()).unwrap();
+        for _ in 0..30 {
+            mkdirat(&fd, name.as_str(), Mode::from_bits_truncate(0o755)).unwrap();
+            fd = openat(&fd, name.as_str(), flags, Mode::empty()).unwrap();
+        }
+        let file = openat(&fd, "leaf", OFlag::O_CREAT | OFlag::O_WRONLY, Mode::S_IRWXU).unwrap();
+        drop(file);
+
+        let lister = Lister::new().path(tmp.path()).tree(true).long(true);
+        let mut out = Vec::new();
+        let errors = lister.render(&mut out).unwrap();
+        assert!(errors.is_empty(), "{errors:?}");
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(&exec("leaf")));
+        assert!(out.ends_with("\n30 directories, 1 file\n"));
+    }
+
+    #[test]
+    fn test_jobs_match_sequential() {
+        let tmp = fixture();
+        // enough entries in each directory to be stat-ed by several threads
+        for dir in ["many_a", "many_b", "many_b/nested"] {
+            let dir = tmp.path().join(dir);
+            fs::create_dir(&dir).unwrap();
+            for ind in 0..100 {
+                let path = dir.join(format!("file{ind:03}"));
+                fs::write(&path, "x".repeat(ind)).unwrap();
+                let mode = if ind % 3 == 0 { 0o755 } else { 0o644 };
+                fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+            }
+        }
+        for lister in [
+            Lister::new().path(tmp.path().join("many_a")).long(true),
+            Lister::new().path(tmp.path().join("many_b")),
+            Lister::new().path(tmp.path()).tree(true).long(true),
+        ] {
+            assert_eq!(render(lister.clone().jobs(8)), render(lister.jobs(1)));
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Recursive disk usage of directories for `--du`.
+
+use crate::lister::open_dir;
+use crate::pathinfo::{Meta, PathInfo, lstat_at};
+use itertools::Itertools;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+/// How the size of a file counts towards disk usage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuMode {
+    /// the number of bytes in the file, like `du --apparent-size`
+    Apparent,
+    /// the space allocated for the file on disk, like `du`
+    Allocated,
+}
+
+impl DuMode {
+    /// The size of the file described by `meta` in this mode, not counting any contents if
+    /// it is a directory.
+    pub fn size(&self, meta: &Meta) -> u64 {
+        match self {
+            Self::Apparent => meta.size,
+            Self::Allocated => meta.blocks * 512,
+        }
+    }
+}
+
+/// A file or directory identified by its device and inode numbers.
+type FileId = (u64, u64);
+
+#[derive(Debug, Default)]
+struct UsageState {
+    /// files with several hard links which have already been counted
+    seen: HashSet<FileId>,
+    /// total usage of every directory walked so far
+    dirs: HashMap<FileId, u64>,
+}
+
+/// Computes the recursive usage of directories, shared by every directory of a listing so
+/// hard links are only counted once and each directory is usually only walked once. The state is
+/// only locked around each lookup and insert, so workers can walk directories in parallel.
+#[derive(Debug)]
+pub(crate) struct DiskUsage {
+    mode: DuMode,
+    one_file_system: bool,
+    state: Mutex<UsageState>,
+}
+
+impl DiskUsage {
+    /// With `one_file_system`, directories on other filesystems than the directory being
+    /// sized are skipped.
+    pub(crate) fn new(mode: DuMode, one_file_system: bool) -> Self {
+        Self {
+            mode,
+            one_file_system,
+            state: Mutex::default(),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, UsageState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// The cached usage of the directory `id`, if it was already walked.
+    fn cached(&self, id: FileId) -> Option<u64> {
+        self.state().dirs.get(&id).copied()
+    }
+
+    /// Check if the file `id` with several hard links has not been counted yet, marking it
+    /// as counted.
+    fn first_seen(&self, id: FileId) -> bool {
+        self.state().seen.insert(id)
+    }
+
+    /// Fill in the usage of each of `paths`, which are children of the open directory `dir`,
+    /// or operands if it is `None`. Directories are walked in name order, so which of them a
+    /// file with several hard links is counted in does not depend on the order of `paths`,
+    /// unless another worker is walking a directory holding it at the same time.
+    pub(crate) fn fill(&self, dir: Option<BorrowedFd<'_>>, paths: &mut [PathInfo]) {
+        let order = (0..paths.len())
+            .sorted_by(|&a, &b| paths[a].cmp(&paths[b]))
+            .collect_vec();
+        for ind in order {
+            let p = &mut paths[ind];
+            let meta = match dir {
+                Some(dir) => p.meta_at(dir),
+                None => p.meta(),
+            };
+            let Some(meta) = meta.copied() else {
+                continue;
+            };
+            let usage = if p.is_dir() {
+                self.dir_usage(dir, &p.path, &meta, meta.dev)
+            } else {
+                self.mode.size(&meta)
+            };
+            p.set_usage(usage);
+        }
+    }
+
+    /// The usage of the directory at `path` in `parent` with metadata `meta`, walking it
+    /// unless it was already walked.
+    fn dir_usage(
+        &self,
+        parent: Option<BorrowedFd<'_>>,
+        path: &Path,
+        meta: &Meta,
+        root_dev: u64,
+    ) -> u64 {
+        match self.cached((meta.dev, meta.ino)) {
+            Some(usage) => usage,
+            None => self.walk(parent, path, meta, root_dev),
+        }
+    }
+
+    /// Sum the usage of the directory at `path` and everything below it in name order, caching
+    /// the total of each directory walked. Entries which cannot be read or stat-ed are left
+    /// out, since the listing itself reports errors for anything it shows.
+    /// If another worker walked the same directory at the same time, the total it cached first
+    /// is kept, so every later lookup agrees.
+    fn walk(&self, parent: Option<BorrowedFd<'_>>, path: &Path, meta: &Meta, root_dev: u64) -> u64 {
+        let mut total = self.mode.size(meta);
+        if let Ok(mut handle) = open_dir(parent, path) {
+            let names = handle
+                .iter()
+                .filter_map(Result::ok)
+                .map(|entry| entry.file_name().to_bytes().to_vec())
+                .filter(|name| name != b"." && name != b"..")
+                .sorted()
+                .collect_vec();
+            let fd = handle.as_fd();
+            for name in names {
+                let name = Path::new(OsStr::from_bytes(&name));
+                let Ok(child) = lstat_at(fd, name) else {
+                    continue;
+                };
+                if child.kind().is_dir() {
+                    if self.one_file_system && child.dev != root_dev {
+                        continue;
+                    }
+                    total += self.dir_usage(Some(fd), &path.join(name), &child, root_dev);
+                } else if child.nlink <= 1 || self.first_seen((child.dev, child.ino)) {
+                    total += self.mode.size(&child);
+                }
+            }
+        }
+        *self
+            .state()
+            .dirs
+            .entry((meta.dev, meta.ino))
+            .or_insert(total)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+
+    #[test]
+    fn test_fill_usage() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("docs/img")).unwrap();
+        fs::write(root.join("docs/guide.md"), "guide".repeat(100)).unwrap();
+        fs::write(root.join("docs/img/logo.png"), "logo").unwrap();
+        // the second link to guide.md is not counted again in img/
+        fs::hard_link(root.join("docs/guide.md"), root.join("docs/img/guide.md")).unwrap();
+        fs::write(root.join("zeta"), "zeta").unwrap();
+        let size = |path: &str| fs::symlink_metadata(root.join(path)).unwrap().size();
+        let img = size("docs/img") + size("docs/img/logo.png");
+        let docs = size("docs") + size("docs/guide.md") + img;
+
+        let usage = DiskUsage::new(DuMode::Apparent, false);
+        let paths = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| {
+                    let path = root.join(name);
+                    let meta = Meta::from(&fs::symlink_metadata(&path).unwrap());
+                    PathInfo::new(path, meta)
+                })
+                .collect_vec()
+        };
+        let mut operands = paths(&["zeta", "docs"]);
+        usage.fill(None, &mut operands);
+        assert_eq!(operands[0].usage(), Some(size("zeta")));
+        assert_eq!(operands[1].usage(), Some(docs));
+        // img/ was cached while walking docs/, so it keeps the total without guide.md
+        let mut operands = paths(&["docs/img"]);
+        usage.fill(None, &mut operands);
+        assert_eq!(operands[0].usage(), Some(img));
+    }
+}
@@ -0,0 +1,40 @@
+use crate::constants::*;
+use std::fmt;
+use std::fmt::Display;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+
+/// Errors encountered while collecting a listing. None of these stop the listing: the affected
+/// path is skipped, and the error is collected so it can be reported alongside the output.
+#[derive(Debug)]
+pub enum ListError {
+    /// `path` could not be accessed
+    Access { path: PathBuf, err: Error },
+    /// an entry could not be read from a directory
+    ReadEntry { err: Error },
+}
+
+/// Formats errors using the messages ls would print, without the program name prefix.
+impl Display for ListError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Access { path, err } => {
+                let why = match err.kind() {
+                    ErrorKind::NotFound => ERR_NO_SUCH_FILE_OR_DIR,
+                    ErrorKind::PermissionDenied => ERR_PERM_DENIED,
+                    _ => "unknown error",
+                };
+                write!(f, "cannot access '{}': {why}", path.display())
+            }
+            Self::ReadEntry { err } => write!(f, "failed reading directory entry: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ListError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Access { err, .. } | Self::ReadEntry { err } => Some(err),
+        }
+    }
+}
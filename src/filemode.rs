@@ -1,5 +1,99 @@
+use nix::dir::Type;
 use std::fmt;
 use std::fmt::Display;
+use std::fs::FileType;
+use std::os::unix::fs::FileTypeExt;
+
+/// The type of a file, as encoded in the file type bits of a mode or reported by a directory
+/// entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+    Fifo,
+    Socket,
+    CharDevice,
+    BlockDevice,
+    Unknown,
+}
+
+impl FileKind {
+    /// Decode the file type bits of `mode`.
+    pub fn from_mode(mode: u32) -> Self {
+        match mode & 0o170000 {
+            0o100000 => Self::File,
+            0o040000 => Self::Dir,
+            0o120000 => Self::Symlink,
+            0o010000 => Self::Fifo,
+            0o140000 => Self::Socket,
+            0o020000 => Self::CharDevice,
+            0o060000 => Self::BlockDevice,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// The character shown for this kind before the permissions in the long format.
+    pub fn symbol(&self) -> char {
+        match self {
+            Self::File | Self::Unknown => '-',
+            Self::Dir => 'd',
+            Self::Symlink => 'l',
+            Self::Fifo => 'p',
+            Self::Socket => 's',
+            Self::CharDevice => 'c',
+            Self::BlockDevice => 'b',
+        }
+    }
+
+    pub fn is_file(&self) -> bool {
+        *self == Self::File
+    }
+
+    pub fn is_dir(&self) -> bool {
+        *self == Self::Dir
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        *self == Self::Symlink
+    }
+}
+
+impl From<FileType> for FileKind {
+    fn from(file_type: FileType) -> Self {
+        if file_type.is_file() {
+            Self::File
+        } else if file_type.is_dir() {
+            Self::Dir
+        } else if file_type.is_symlink() {
+            Self::Symlink
+        } else if file_type.is_fifo() {
+            Self::Fifo
+        } else if file_type.is_socket() {
+            Self::Socket
+        } else if file_type.is_char_device() {
+            Self::CharDevice
+        } else if file_type.is_block_device() {
+            Self::BlockDevice
+        } else {
+            Self::Unknown
+        }
+    }
+}
+
+impl From<Type> for FileKind {
+    fn from(file_type: Type) -> Self {
+        match file_type {
+            Type::File => Self::File,
+            Type::Directory => Self::Dir,
+            Type::Symlink => Self::Symlink,
+            Type::Fifo => Self::Fifo,
+            Type::Socket => Self::Socket,
+            Type::CharacterDevice => Self::CharDevice,
+            Type::BlockDevice => Self::BlockDevice,
+        }
+    }
+}
 
 /// Stores the file mode obtained from `fs::mode()` or `fs::st_mode()`.
 pub struct FileMode(pub u32);
@@ -43,47 +137,44 @@ impl FileMode {
     }
 }
 
-/// Displays the mode using ls character symbols. This implementation does not fully replicate
-/// ls output because it will never output `S` for the setuid or setgid bits.
-///
-/// See this [stackexchange answer](https://unix.stackexchange.com/a/28412) for more details about `s` vs `S`
+/// Displays the permission bits like ls, e.g. `rwxr-sr-t`. The execute positions show `s`
+/// for setuid or setgid and `t` for the sticky bit, in upper case when the execute bit they
+/// share is not set.
 impl Display for FileMode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let user_write = if self.user_write() { "w" } else { "-" };
-        let user_read = if self.user_read() { "r" } else { "-" };
-        let user_execute = if self.suid_bit() {
-            "s"
-        } else if self.user_execute() {
-            "x"
-        } else {
-            "-"
+        let bit = |set: bool, c: char| if set { c } else { '-' };
+        let special = |special: bool, execute: bool, c: char| match (special, execute) {
+            (true, true) => c,
+            (true, false) => c.to_ascii_uppercase(),
+            (false, execute) => bit(execute, 'x'),
         };
-        let group_write = if self.group_write() { "w" } else { "-" };
-        let group_read = if self.group_read() { "r" } else { "-" };
-        let group_execute = if self.sgid_bit() {
-            "s"
-        } else if self.group_execute() {
-            "x"
-        } else {
-            "-"
-        };
-        let other_write = if self.other_write() { "w" } else { "-" };
-        let other_read = if self.other_read() { "r" } else { "-" };
-        let other_execute = if self.other_execute() { "x" } else { "-" };
-        let sticky_bit = if self.sticky_bit() { "t" } else { "." };
         write!(
             f,
-            "{}{}{}{}{}{}{}{}{}{}",
-            user_write,
-            user_read,
-            user_execute,
-            group_write,
-            group_read,
-            group_execute,
-            other_write,
-            other_read,
-            other_execute,
-            sticky_bit
+            "{}{}{}{}{}{}{}{}{}",
+            bit(self.user_read(), 'r'),
+            bit(self.user_write(), 'w'),
+            special(self.suid_bit(), self.user_execute(), 's'),
+            bit(self.group_read(), 'r'),
+            bit(self.group_write(), 'w'),
+            special(self.sgid_bit(), self.group_execute(), 's'),
+            bit(self.other_read(), 'r'),
+            bit(self.other_write(), 'w'),
+            special(self.sticky_bit(), self.other_execute(), 't'),
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_display_mode() {
+        assert_eq!(FileMode(0o100644).to_string(), "rw-r--r--");
+        assert_eq!(FileMode(0o755).to_string(), "rwxr-xr-x");
+        assert_eq!(FileMode(0o6755).to_string(), "rwsr-sr-x");
+        assert_eq!(FileMode(0o6644).to_string(), "rwSr-Sr--");
+        assert_eq!(FileMode(0o1777).to_string(), "rwxrwxrwt");
+        assert_eq!(FileMode(0o1770).to_string(), "rwxrwx--T");
+    }
+}
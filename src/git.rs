@@ -0,0 +1,303 @@
+//! Git status of listed entries for `--git`.
+
+#[cfg(feature = "git")]
+pub(crate) use repos::GitRepos;
+
+/// The status of an entry in one of the index or the work tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GitStatus {
+    Unmodified,
+    New,
+    Modified,
+    Deleted,
+    Renamed,
+    TypeChange,
+    Ignored,
+    Conflicted,
+}
+
+impl GitStatus {
+    /// The character shown for this status in the git column.
+    pub fn symbol(&self) -> char {
+        match self {
+            Self::Unmodified => '-',
+            Self::New => 'N',
+            Self::Modified => 'M',
+            Self::Deleted => 'D',
+            Self::Renamed => 'R',
+            Self::TypeChange => 'T',
+            Self::Ignored => 'I',
+            Self::Conflicted => 'U',
+        }
+    }
+}
+
+/// The status of an entry relative to `HEAD` in the index, and relative to the index in the
+/// work tree. Directories combine the statuses of everything below them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GitFileStatus {
+    pub staged: GitStatus,
+    pub unstaged: GitStatus,
+}
+
+impl GitFileStatus {
+    pub fn is_ignored(&self) -> bool {
+        self.unstaged == GitStatus::Ignored
+    }
+}
+
+#[cfg(feature = "git")]
+impl From<git2::Status> for GitFileStatus {
+    /// When several bits are set, e.g. for directories, the first in the order git lists them
+    /// is shown.
+    fn from(status: git2::Status) -> Self {
+        use git2::Status;
+        if status.is_conflicted() {
+            return Self {
+                staged: GitStatus::Conflicted,
+                unstaged: GitStatus::Conflicted,
+            };
+        }
+        let pick = |bits: [(Status, GitStatus); 5]| {
+            bits.into_iter()
+                .find(|(bit, _)| status.contains(*bit))
+                .map_or(GitStatus::Unmodified, |(_, s)| s)
+        };
+        let staged = pick([
+            (Status::INDEX_NEW, GitStatus::New),
+            (Status::INDEX_MODIFIED, GitStatus::Modified),
+            (Status::INDEX_DELETED, GitStatus::Deleted),
+            (Status::INDEX_RENAMED, GitStatus::Renamed),
+            (Status::INDEX_TYPECHANGE, GitStatus::TypeChange),
+        ]);
+        let unstaged = match pick([
+            (Status::WT_NEW, GitStatus::New),
+            (Status::WT_MODIFIED, GitStatus::Modified),
+            (Status::WT_DELETED, GitStatus::Deleted),
+            (Status::WT_RENAMED, GitStatus::Renamed),
+            (Status::WT_TYPECHANGE, GitStatus::TypeChange),
+        ]) {
+            GitStatus::Unmodified if status.is_ignored() => GitStatus::Ignored,
+            unstaged => unstaged,
+        };
+        Self { staged, unstaged }
+    }
+}
+
+#[cfg(feature = "git")]
+mod repos {
+    use super::GitFileStatus;
+    use git2::{Repository, Status, StatusOptions};
+    use std::collections::HashMap;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+
+    /// The statuses of every changed, untracked or ignored path in a work tree.
+    #[derive(Debug)]
+    struct RepoStatus {
+        /// paths relative to the work tree, sorted so everything below a directory follows it
+        statuses: Vec<(PathBuf, Status)>,
+    }
+
+    impl RepoStatus {
+        /// Compute the status of `repo` the way `git status --ignored` does, detecting renames
+        /// in both the index and the work tree. Ignored directories are not descended into.
+        fn new(repo: &Repository) -> Option<Self> {
+            let mut opts = StatusOptions::new();
+            opts.include_untracked(true)
+                .recurse_untracked_dirs(true)
+                .include_ignored(true)
+                .recurse_ignored_dirs(false)
+                .renames_head_to_index(true)
+                .renames_index_to_workdir(true);
+            let statuses = repo.statuses(Some(&mut opts)).ok()?;
+            let mut statuses = statuses
+                .iter()
+                .map(|entry| {
+                    // renamed entries are listed under their new path
+                    let new_path = entry
+                        .index_to_workdir()
+                        .or_else(|| entry.head_to_index())
+                        .and_then(|delta| delta.new_file().path().map(Path::to_path_buf));
+                    let path = new_path
+                        .unwrap_or_else(|| PathBuf::from(OsStr::from_bytes(entry.path_bytes())));
+                    (path, entry.status())
+                })
+                .collect::<Vec<_>>();
+            statuses.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Some(Self { statuses })
+        }
+
+        fn get(&self, rel: &Path) -> Option<Status> {
+            self.statuses
+                .binary_search_by(|(p, _)| p.as_path().cmp(rel))
+                .ok()
+                .map(|ind| self.statuses[ind].1)
+        }
+
+        /// The status of `rel`, combining the statuses of everything below it. Only paths
+        /// which are ignored themselves, or are below an ignored directory, count as ignored.
+        fn status(&self, rel: &Path) -> GitFileStatus {
+            let ignored_parent = rel
+                .ancestors()
+                .skip(1)
+                .filter(|a| !a.as_os_str().is_empty())
+                .any(|a| self.get(a).is_some_and(|s| s.is_ignored()));
+            if ignored_parent {
+                return GitFileStatus::from(Status::IGNORED);
+            }
+            let start = self.statuses.partition_point(|(p, _)| p.as_path() < rel);
+            let status = self.statuses[start..]
+                .iter()
+                .take_while(|(p, _)| p.starts_with(rel))
+                .fold(Status::CURRENT, |acc, (p, s)| {
+                    if p == rel {
+                        acc | *s
+                    } else {
+                        acc | (*s - Status::IGNORED)
+                    }
+                });
+            GitFileStatus::from(status)
+        }
+    }
+
+    /// The work tree and path within it of a listed directory.
+    type Location = Option<(Arc<RepoStatus>, PathBuf)>;
+
+    /// Finds the repository of each listed directory, computing the status of each work tree
+    /// once per listing.
+    #[derive(Debug, Default)]
+    pub(crate) struct GitRepos {
+        dirs: Mutex<HashMap<PathBuf, Location>>,
+        repos: Mutex<HashMap<PathBuf, Arc<RepoStatus>>>,
+    }
+
+    impl GitRepos {
+        /// The status of `path`, or `None` if it is not inside a work tree.
+        pub(crate) fn status(&self, path: &Path) -> Option<GitFileStatus> {
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            let (repo, rel_dir) = self.locate(dir)?;
+            Some(repo.status(&rel_dir.join(path.file_name()?)))
+        }
+
+        fn locate(&self, dir: &Path) -> Location {
+            let mut dirs = self.dirs.lock().unwrap_or_else(|err| err.into_inner());
+            if let Some(location) = dirs.get(dir) {
+                return location.clone();
+            }
+            let location = self.discover(dir);
+            dirs.insert(dir.to_path_buf(), location.clone());
+            location
+        }
+
+        fn discover(&self, dir: &Path) -> Location {
+            let dir = dir.canonicalize().ok()?;
+            let repo = Repository::discover(&dir).ok()?;
+            let workdir = repo.workdir()?.canonicalize().ok()?;
+            let rel_dir = dir.strip_prefix(&workdir).ok()?.to_path_buf();
+            let mut repos = self.repos.lock().unwrap_or_else(|err| err.into_inner());
+            let status = match repos.get(&workdir) {
+                Some(status) => status.clone(),
+                None => {
+                    let status = Arc::new(RepoStatus::new(&repo)?);
+                    repos.insert(workdir, status.clone());
+                    status
+                }
+            };
+            Some((status, rel_dir))
+        }
+    }
+}
+
+#[cfg(all(test, feature = "git"))]
+mod test {
+    use crate::display::test::render;
+    use crate::lister::Lister;
+    use git2::{Repository, Signature};
+    use std::fs;
+    use std::path::Path;
+
+    /// Commit `kept`, `renamed`, `sub/clean` and a `.gitignore`, then modify `kept`, stage
+    /// renaming `renamed` to `moved`, and add `sub/staged`, `untracked` and ignored files.
+    fn repo() -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let repo = Repository::init(root).unwrap();
+        fs::create_dir(root.join("sub")).unwrap();
+        for name in ["kept", "renamed", "sub/clean"] {
+            fs::write(root.join(name), name.repeat(20)).unwrap();
+        }
+        fs::write(root.join(".gitignore"), "*.log\ntarget/\n").unwrap();
+        let mut index = repo.index().unwrap();
+        for name in [".gitignore", "kept", "renamed", "sub/clean"] {
+            index.add_path(Path::new(name)).unwrap();
+        }
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = Signature::now("rusl", "rusl@example.com").unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "initial", &tree, &[])
+            .unwrap();
+
+        fs::write(root.join("kept"), "changed").unwrap();
+        fs::rename(root.join("renamed"), root.join("moved")).unwrap();
+        fs::write(root.join("sub/staged"), "staged").unwrap();
+        index.remove_path(Path::new("renamed")).unwrap();
+        index.add_path(Path::new("moved")).unwrap();
+        index.add_path(Path::new("sub/staged")).unwrap();
+        index.write().unwrap();
+        fs::write(root.join("untracked"), "untracked").unwrap();
+        fs::write(root.join("debug.log"), "log").unwrap();
+        fs::create_dir(root.join("target")).unwrap();
+        fs::write(root.join("target/out"), "out").unwrap();
+        tmp
+    }
+
+    #[test]
+    fn test_git_status() {
+        let tmp = repo();
+        let lister = Lister::new()
+            .path(tmp.path())
+            .one_per_line(true)
+            .color(false)
+            .git(true);
+        let out = render(lister.clone());
+        assert_eq!(
+            out,
+            "-I debug.log\n-M kept\nR- moved\nN- sub/\n-I target/\n-N untracked\n"
+        );
+
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("plain"), "plain").unwrap();
+        let out = render(
+            lister
+                .clone()
+                .path(tmp.path().join("sub"))
+                .path(outside.path()),
+        );
+        assert!(out.contains("\n-- clean\nN- staged\n"), "{out}");
+        // entries outside a work tree have no status
+        assert!(out.contains(":\nplain\n"), "{out}");
+
+        let out = render(lister.git_ignore(true).tree(true));
+        assert!(!out.contains(".log") && !out.contains("target"), "{out}");
+        assert!(out.contains("└── N- staged\n"), "{out}");
+    }
+
+    #[test]
+    fn test_git_status_in_grid() {
+        let tmp = repo();
+        let lister = Lister::new()
+            .path(tmp.path().join("sub"))
+            .color(false)
+            .git(true);
+        // the status counts towards the width of each column
+        let out = render(lister.clone().term_cols(20));
+        assert_eq!(out, "-- clean  N- staged\n");
+        let out = render(lister.term_cols(19));
+        assert_eq!(out, "-- clean\nN- staged\n");
+    }
+}
@@ -0,0 +1,92 @@
+//! `file://` URLs for linking entries with OSC 8 escape sequences for `--hyperlink`.
+
+use nix::unistd::gethostname;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{self, Path};
+use std::sync::OnceLock;
+
+/// Percent-encode `bytes` for a URL, keeping only unreserved characters and `/` as they are.
+fn percent_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len());
+    for &b in bytes {
+        if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    encoded
+}
+
+/// The name of this host, which terminals compare with their own to check that a link points
+/// to a local file. Only looked up once.
+fn hostname() -> &'static str {
+    static HOSTNAME: OnceLock<String> = OnceLock::new();
+    HOSTNAME.get_or_init(|| {
+        gethostname()
+            .map(|name| percent_encode(name.as_bytes()))
+            .unwrap_or_default()
+    })
+}
+
+/// The `file://hostname/abs/path` URL of `path`, made absolute without resolving symlinks so
+/// the link leads to the entry itself. Returns `None` if the working directory is unknown.
+pub fn file_url(path: &Path) -> Option<String> {
+    let path = path::absolute(path).ok()?;
+    Some(format!(
+        "file://{}{}",
+        hostname(),
+        percent_encode(path.as_os_str().as_bytes())
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::display::test::{fixture, render};
+    use crate::lister::Lister;
+
+    #[test]
+    fn test_file_url() {
+        let url = file_url(Path::new("/tmp/a b/100%/ünï.rs")).unwrap();
+        let path = url.strip_prefix("file://").unwrap();
+        assert_eq!(
+            path.strip_prefix(hostname()),
+            Some("/tmp/a%20b/100%25/%C3%BCn%C3%AF.rs")
+        );
+    }
+
+    #[test]
+    fn test_hyperlinks() {
+        let tmp = fixture();
+        let lister = Lister::new().path(tmp.path()).color(false);
+        let strip_links = |out: &str| {
+            let mut stripped = String::new();
+            for (ind, part) in out.split("\x1b]8;;").enumerate() {
+                // every escape sequence ends with a string terminator
+                let text = if ind == 0 {
+                    part
+                } else {
+                    part.split_once("\x1b\\").unwrap().1
+                };
+                stripped.push_str(text);
+            }
+            stripped
+        };
+        for lister in [
+            lister.clone().term_cols(30),
+            lister.clone().term_cols(30).by_lines(true),
+            lister.clone().long(true),
+            lister.clone().tree(true),
+        ] {
+            let out = render(lister.clone().hyperlink(true));
+            let url = file_url(&tmp.path().join("beta.txt")).unwrap();
+            assert!(
+                out.contains(&format!("\x1b]8;;{url}\x1b\\beta.txt\x1b]8;;\x1b\\")),
+                "{out}"
+            );
+            // the escape sequences do not change the layout
+            assert_eq!(strip_links(&out), render(lister));
+        }
+    }
+}
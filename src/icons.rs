@@ -0,0 +1,188 @@
+//! Nerd Font glyphs shown before entries for `--icons`.
+
+use crate::filemode::FileKind;
+use std::collections::HashMap;
+
+/// Glyphs for file types, used when neither the name nor the extension of an entry has one.
+const KIND_ICONS: &[(&str, &str)] = &[
+    ("di", "\u{f115}"),
+    ("fi", "\u{f15b}"),
+    ("ln", "\u{f481}"),
+    ("ex", "\u{f489}"),
+    ("pi", "\u{f0232}"),
+    ("so", "\u{f0427}"),
+    ("cd", "\u{f0b7a}"),
+    ("bd", "\u{f0a0}"),
+];
+
+/// Glyphs for well-known file names, which take precedence over extensions.
+const NAME_ICONS: &[(&str, &str)] = &[
+    (".git", "\u{f1d3}"),
+    (".gitattributes", "\u{f1d3}"),
+    (".gitignore", "\u{f1d3}"),
+    (".gitmodules", "\u{f1d3}"),
+    ("Cargo.lock", "\u{e7a8}"),
+    ("Cargo.toml", "\u{e7a8}"),
+    ("Dockerfile", "\u{f308}"),
+    ("LICENSE", "\u{f0219}"),
+    ("Makefile", "\u{f489}"),
+    ("README.md", "\u{f48a}"),
+    ("node_modules", "\u{e718}"),
+];
+
+/// Glyphs for lowercase file extensions.
+const EXTENSION_ICONS: &[(&str, &str)] = &[
+    ("c", "\u{e61e}"),
+    ("cpp", "\u{e61d}"),
+    ("css", "\u{e749}"),
+    ("gif", "\u{f1c5}"),
+    ("go", "\u{e626}"),
+    ("gz", "\u{f410}"),
+    ("h", "\u{f0fd}"),
+    ("html", "\u{f13b}"),
+    ("java", "\u{e256}"),
+    ("jpg", "\u{f1c5}"),
+    ("js", "\u{e74e}"),
+    ("json", "\u{e60b}"),
+    ("lock", "\u{f023}"),
+    ("md", "\u{f48a}"),
+    ("pdf", "\u{f1c1}"),
+    ("png", "\u{f1c5}"),
+    ("py", "\u{e606}"),
+    ("rs", "\u{e7a8}"),
+    ("sh", "\u{f489}"),
+    ("svg", "\u{f1c5}"),
+    ("tar", "\u{f410}"),
+    ("toml", "\u{e615}"),
+    ("ts", "\u{e628}"),
+    ("txt", "\u{f15c}"),
+    ("xz", "\u{f410}"),
+    ("yaml", "\u{e6a8}"),
+    ("yml", "\u{e6a8}"),
+    ("zip", "\u{f410}"),
+];
+
+/// The glyphs shown for entries, looked up by file name, then extension, then file type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Icons {
+    kinds: HashMap<String, String>,
+    names: HashMap<String, String>,
+    extensions: HashMap<String, String>,
+}
+
+impl Default for Icons {
+    fn default() -> Self {
+        let map = |icons: &[(&str, &str)]| {
+            icons
+                .iter()
+                .map(|&(key, icon)| (key.to_string(), icon.to_string()))
+                .collect()
+        };
+        Self {
+            kinds: map(KIND_ICONS),
+            names: map(NAME_ICONS),
+            extensions: map(EXTENSION_ICONS),
+        }
+    }
+}
+
+impl Icons {
+    /// Override the default glyphs using `spec`, a `:` separated list of `key=glyph` entries in
+    /// the style of `LS_COLORS`. Keys are either `*.ext` for an extension, one of the file type
+    /// codes `di`, `fi`, `ln`, `ex`, `pi`, `so`, `cd` and `bd`, or else a file name. Malformed
+    /// entries are skipped.
+    pub fn with_overrides(mut self, spec: &str) -> Self {
+        for (key, icon) in spec.split(':').filter_map(|entry| entry.split_once('=')) {
+            if let Some(ext) = key.strip_prefix("*.") {
+                self.extensions.insert(ext.to_lowercase(), icon.to_string());
+            } else if self.kinds.contains_key(key) {
+                self.kinds.insert(key.to_string(), icon.to_string());
+            } else if !key.is_empty() {
+                self.names.insert(key.to_string(), icon.to_string());
+            }
+        }
+        self
+    }
+
+    /// The glyph for an entry called `name` of type `kind`, which is `executable` if it is a
+    /// regular file with an execute bit set.
+    pub fn icon(&self, name: &str, kind: FileKind, executable: bool) -> &str {
+        if let Some(icon) = self.names.get(name) {
+            return icon;
+        }
+        let code = match kind {
+            FileKind::Dir => "di",
+            FileKind::Symlink => "ln",
+            FileKind::Fifo => "pi",
+            FileKind::Socket => "so",
+            FileKind::CharDevice => "cd",
+            FileKind::BlockDevice => "bd",
+            FileKind::File if executable => "ex",
+            FileKind::File | FileKind::Unknown => "fi",
+        };
+        let extension = name
+            .rsplit_once('.')
+            .filter(|(stem, _)| !stem.is_empty())
+            .map(|(_, ext)| ext.to_lowercase());
+        let by_extension = extension
+            .filter(|_| kind.is_file())
+            .and_then(|ext| self.extensions.get(&ext));
+        by_extension
+            .or_else(|| self.kinds.get(code))
+            .map_or("", String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::display::test::{fixture, render};
+    use crate::lister::Lister;
+
+    #[test]
+    fn test_icon_lookup() {
+        let icons = Icons::default();
+        assert_eq!(icons.icon("Cargo.toml", FileKind::File, false), "\u{e7a8}");
+        assert_eq!(icons.icon("main.RS", FileKind::File, false), "\u{e7a8}");
+        assert_eq!(icons.icon(".git", FileKind::Dir, false), "\u{f1d3}");
+        assert_eq!(icons.icon("src", FileKind::Dir, false), "\u{f115}");
+        // extensions only apply to regular files, and dotfiles have no extension
+        assert_eq!(icons.icon("lib.rs", FileKind::Dir, false), "\u{f115}");
+        assert_eq!(icons.icon(".rs", FileKind::File, false), "\u{f15b}");
+        assert_eq!(icons.icon("run", FileKind::File, true), "\u{f489}");
+        assert_eq!(icons.icon("link", FileKind::Symlink, false), "\u{f481}");
+    }
+
+    #[test]
+    fn test_icon_overrides() {
+        let icons = Icons::default().with_overrides("*.RS=R:di=D:Makefile=M:junk:=x");
+        assert_eq!(icons.icon("main.rs", FileKind::File, false), "R");
+        assert_eq!(icons.icon("src", FileKind::Dir, false), "D");
+        assert_eq!(icons.icon("Makefile", FileKind::File, false), "M");
+        assert_eq!(icons.icon("notes.md", FileKind::File, false), "\u{f48a}");
+    }
+
+    #[test]
+    fn test_icon_widths() {
+        let tmp = fixture();
+        let lister = Lister::new()
+            .path(tmp.path())
+            .color(false)
+            .icons(Some(Icons::default()));
+        // each glyph may take up two cells, so it counts as three with the space after it
+        assert_eq!(
+            render(lister.clone().term_cols(61)),
+            concat!(
+                "\u{f15b} alpha  \u{f15c} beta.txt  \u{f115} docs/  ",
+                "\u{f481} link  \u{f489} run.sh  \u{f15b} zeta\n",
+            )
+        );
+        assert_eq!(
+            render(lister.term_cols(60)),
+            concat!(
+                "\u{f15b} alpha     \u{f115} docs/  \u{f489} run.sh\n",
+                "\u{f15c} beta.txt  \u{f481} link   \u{f15b} zeta\n",
+            )
+        );
+    }
+}
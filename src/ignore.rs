@@ -0,0 +1,432 @@
+//! Hiding files matched by `.gitignore`, `.ignore` and git's exclude files for
+//! `--ignore-vcs`.
+//!
+//! Ignore files are scoped like git scopes them: patterns apply to the directory containing
+//! their file and everything below it, deeper files take precedence over shallower ones, and
+//! `.ignore` takes precedence over `.gitignore` in the same directory. `.git/info/exclude` and
+//! the global excludes file apply to the whole work tree with the lowest precedence.
+
+use nix::fcntl::{OFlag, openat};
+use nix::sys::stat::Mode;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::BorrowedFd;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Ignore files read in every directory, from lowest to highest precedence.
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// A single pattern from an ignore file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    glob: Vec<u8>,
+    /// `!pattern`, which re-includes paths excluded by an earlier pattern
+    negated: bool,
+    /// `pattern/`, which only matches directories
+    dir_only: bool,
+    /// patterns containing a `/` match the path relative to the ignore file, while others
+    /// match the file name at any depth
+    anchored: bool,
+}
+
+impl Rule {
+    /// Parse a line of an ignore file, returning `None` for blank lines and comments.
+    fn parse(line: &[u8]) -> Option<Self> {
+        let mut line = line.strip_suffix(b"\r").unwrap_or(line);
+        // trailing spaces are ignored unless escaped
+        while let Some(rest) = line.strip_suffix(b" ") {
+            if rest.ends_with(b"\\") {
+                break;
+            }
+            line = rest;
+        }
+        if line.is_empty() || line.starts_with(b"#") {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix(b"!") {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix(b"/") {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains(&b'/');
+        let glob = line.strip_prefix(b"/").unwrap_or(line);
+        if glob.is_empty() {
+            return None;
+        }
+        Some(Self {
+            glob: glob.to_vec(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    /// Check if the rule matches `rel`, a path relative to the directory of its ignore file.
+    fn matches(&self, rel: &[u8], is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            glob_match(&self.glob, rel)
+        } else {
+            let name = rel.rsplit(|&c| c == b'/').next().unwrap_or(rel);
+            glob_match(&self.glob, name)
+        }
+    }
+}
+
+/// Match `s` against the gitignore glob `p`. `*`, `?` and classes never match `/`, while `**`
+/// matches across directories when it is a whole path component.
+fn glob_match(p: &[u8], s: &[u8]) -> bool {
+    match p {
+        [] => s.is_empty(),
+        [b'*', b'*'] => true,
+        [b'*', b'*', b'/', rest @ ..] => {
+            // zero or more directories
+            glob_match(rest, s)
+                || s.iter()
+                    .enumerate()
+                    .any(|(ind, &c)| c == b'/' && glob_match(rest, &s[ind + 1..]))
+        }
+        [b'*', rest @ ..] => {
+            let rest = match rest.iter().position(|&c| c != b'*') {
+                Some(ind) => &rest[ind..],
+                None => &[],
+            };
+            for ind in 0..=s.len() {
+                if glob_match(rest, &s[ind..]) {
+                    return true;
+                }
+                if s.get(ind) == Some(&b'/') {
+                    break;
+                }
+            }
+            false
+        }
+        [b'?', rest @ ..] => matches!(s, [c, tail @ ..] if *c != b'/' && glob_match(rest, tail)),
+        [b'[', rest @ ..] => match (class_match(rest, s.first().copied()), s) {
+            (Some((true, len)), [_, tail @ ..]) => glob_match(&rest[len..], tail),
+            (Some(_), _) => false,
+            // an unterminated class is a literal `[`
+            (None, [b'[', tail @ ..]) => glob_match(rest, tail),
+            (None, _) => false,
+        },
+        [b'\\', c, rest @ ..] => matches!(s, [d, tail @ ..] if d == c && glob_match(rest, tail)),
+        [c, rest @ ..] => matches!(s, [d, tail @ ..] if d == c && glob_match(rest, tail)),
+    }
+}
+
+/// Match `c` against the class starting after its `[` in `p`. Returns whether it matched and
+/// the length of the class including its closing `]`, or `None` if the class is unterminated.
+fn class_match(p: &[u8], c: Option<u8>) -> Option<(bool, usize)> {
+    let (negated, start) = match p.first() {
+        Some(b'!' | b'^') => (true, 1),
+        _ => (false, 0),
+    };
+    let mut matched = false;
+    let mut ind = start;
+    loop {
+        let lo = *p.get(ind)?;
+        // `]` is a literal when it comes first
+        if lo == b']' && ind > start {
+            break;
+        }
+        match (p.get(ind + 1), p.get(ind + 2)) {
+            (Some(b'-'), Some(&hi)) if hi != b']' => {
+                matched |= c.is_some_and(|c| (lo..=hi).contains(&c));
+                ind += 3;
+            }
+            _ => {
+                matched |= c == Some(lo);
+                ind += 1;
+            }
+        }
+    }
+    let matched = c.is_some_and(|c| c != b'/') && matched != negated;
+    Some((matched, ind + 1))
+}
+
+/// The rules of one ignore file.
+#[derive(Debug)]
+struct RuleSet {
+    /// directory the rules are relative to, relative to the root of the work tree
+    base: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    fn parse(base: PathBuf, contents: &[u8]) -> Option<Arc<Self>> {
+        let rules = contents
+            .split(|&c| c == b'\n')
+            .filter_map(Rule::parse)
+            .collect::<Vec<_>>();
+        (!rules.is_empty()).then(|| Arc::new(Self { base, rules }))
+    }
+
+    /// Whether the last rule matching `rel` ignores it, or `None` if no rule matches.
+    fn decide(&self, rel: &Path, is_dir: bool) -> Option<bool> {
+        let rel = rel.strip_prefix(&self.base).ok()?.as_os_str().as_bytes();
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(rel, is_dir))
+            .map(|rule| !rule.negated)
+    }
+}
+
+/// The ignore rules in scope for the entries of a directory.
+#[derive(Debug, Clone)]
+pub(crate) struct DirIgnores {
+    /// the directory, relative to the root of its work tree
+    rel: PathBuf,
+    /// rule sets from lowest to highest precedence
+    sets: Vec<Arc<RuleSet>>,
+}
+
+impl DirIgnores {
+    /// The rules in scope for the listed directory `dir`, open as `fd`. Inside a work tree
+    /// this includes the global excludes, `.git/info/exclude`, and the ignore files of every
+    /// directory from the root of the work tree down to `dir`.
+    pub(crate) fn for_operand(dir: &Path, fd: BorrowedFd<'_>) -> Self {
+        let canonical = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        let root = canonical.ancestors().find(|a| a.join(".git").exists());
+        let mut sets = Vec::new();
+        let Some(root) = root else {
+            let mut ignores = Self {
+                rel: PathBuf::new(),
+                sets,
+            };
+            ignores.read_dir_files(fd);
+            return ignores;
+        };
+
+        let git_dir = root.join(".git");
+        let global = excludes_file(&git_dir).and_then(|path| fs::read(path).ok());
+        let exclude = fs::read(git_dir.join("info/exclude")).ok();
+        sets.extend(
+            [global, exclude]
+                .into_iter()
+                .flatten()
+                .filter_map(|contents| RuleSet::parse(PathBuf::new(), &contents)),
+        );
+        let rel = canonical.strip_prefix(root).unwrap_or(Path::new(""));
+        // the ignore files of the ancestors of `dir` within the work tree
+        let mut ancestors = rel.ancestors().skip(1).collect::<Vec<_>>();
+        ancestors.reverse();
+        for ancestor in ancestors {
+            for name in IGNORE_FILES {
+                if let Ok(contents) = fs::read(root.join(ancestor).join(name)) {
+                    sets.extend(RuleSet::parse(ancestor.to_path_buf(), &contents));
+                }
+            }
+        }
+        let mut ignores = Self {
+            rel: rel.to_path_buf(),
+            sets,
+        };
+        ignores.read_dir_files(fd);
+        ignores
+    }
+
+    /// The rules in scope for the subdirectory `name` of this directory, open as `fd`.
+    pub(crate) fn descend(&self, name: &OsStr, fd: BorrowedFd<'_>) -> Self {
+        let mut ignores = Self {
+            rel: self.rel.join(name),
+            sets: self.sets.clone(),
+        };
+        ignores.read_dir_files(fd);
+        ignores
+    }
+
+    /// Add the rules of the ignore files inside the directory open as `fd`.
+    fn read_dir_files(&mut self, fd: BorrowedFd<'_>) {
+        for name in IGNORE_FILES {
+            let Ok(file) = openat(fd, name, OFlag::O_RDONLY | OFlag::O_CLOEXEC, Mode::empty())
+            else {
+                continue;
+            };
+            let mut contents = Vec::new();
+            if File::from(file).read_to_end(&mut contents).is_ok() {
+                self.sets
+                    .extend(RuleSet::parse(self.rel.clone(), &contents));
+            }
+        }
+    }
+
+    /// Check if the entry `name` of this directory is ignored.
+    pub(crate) fn is_ignored(&self, name: &OsStr, is_dir: bool) -> bool {
+        let rel = self.rel.join(name);
+        self.sets
+            .iter()
+            .rev()
+            .find_map(|set| set.decide(&rel, is_dir))
+            .unwrap_or(false)
+    }
+}
+
+/// The global excludes file: `core.excludesFile` from the repository or user git config, or
+/// else `$XDG_CONFIG_HOME/git/ignore`.
+fn excludes_file(git_dir: &Path) -> Option<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let xdg = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|home| home.join(".config")));
+    // later files take precedence, like git reads them
+    let configs = [
+        xdg.as_ref().map(|xdg| xdg.join("git/config")),
+        home.as_ref().map(|home| home.join(".gitconfig")),
+        Some(git_dir.join("config")),
+    ];
+    let configured = configs
+        .into_iter()
+        .flatten()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .filter_map(|config| config_excludes_file(&config))
+        .next_back();
+    match configured {
+        Some(path) => match (path.strip_prefix("~/"), &home) {
+            (Some(rest), Some(home)) => Some(home.join(rest)),
+            _ => Some(PathBuf::from(path)),
+        },
+        None => xdg.map(|xdg| xdg.join("git/ignore")),
+    }
+}
+
+/// Find `core.excludesFile` in the contents of a git config file.
+fn config_excludes_file(config: &str) -> Option<String> {
+    let mut in_core = false;
+    let mut found = None;
+    for line in config.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_core = line.eq_ignore_ascii_case("[core]");
+        } else if let Some((key, value)) = line.split_once('=')
+            && in_core
+            && key.trim().eq_ignore_ascii_case("excludesfile")
+        {
+            found = Some(value.trim().trim_matches('"').to_string());
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::display::test::render;
+    use crate::lister::Lister;
+
+    #[test]
+    fn test_glob_match() {
+        for (glob, path, expected) in [
+            ("*.log", "debug.log", true),
+            ("*.log", "dir/debug.log", false),
+            ("debug?.log", "debug1.log", true),
+            ("debug?.log", "debug10.log", false),
+            ("debug[0-9].log", "debug3.log", true),
+            ("debug[!0-9].log", "debug3.log", false),
+            ("debug[!0-9].log", "debuga.log", true),
+            ("[]].txt", "].txt", true),
+            ("[unterminated", "[unterminated", true),
+            ("**/logs", "logs", true),
+            ("**/logs", "a/b/logs", true),
+            ("logs/**", "logs/a/b", true),
+            ("a/**/b", "a/b", true),
+            ("a/**/b", "a/x/y/b", true),
+            ("a/**/b", "a/x/y/c", false),
+            ("a/*/b", "a/x/y/b", false),
+            ("\\#literal", "#literal", true),
+        ] {
+            assert_eq!(
+                glob_match(glob.as_bytes(), path.as_bytes()),
+                expected,
+                "{glob} {path}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_rule_parse() {
+        assert_eq!(Rule::parse(b"# comment"), None);
+        assert_eq!(Rule::parse(b"   "), None);
+        let rule = Rule::parse(b"!/build/  ").unwrap();
+        assert!(rule.negated && rule.dir_only && rule.anchored);
+        assert_eq!(rule.glob, b"build");
+        let rule = Rule::parse(b"target/").unwrap();
+        assert!(rule.dir_only && !rule.anchored);
+        assert!(rule.matches(b"a/target", true));
+        assert!(!rule.matches(b"a/target", false));
+        assert_eq!(Rule::parse(b"trailing\\ ").unwrap().glob, b"trailing\\ ");
+    }
+
+    #[test]
+    fn test_config_excludes_file() {
+        let config = "[user]\n\texcludesfile = nope\n[core]\n\tExcludesFile = \"~/.ignore\"\n";
+        assert_eq!(config_excludes_file(config), Some("~/.ignore".to_string()));
+        assert_eq!(config_excludes_file("[core]\n\teditor = vi\n"), None);
+    }
+
+    #[test]
+    fn test_ignore_vcs_scoping() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join(".git/info")).unwrap();
+        fs::create_dir_all(root.join("sub/build")).unwrap();
+        fs::create_dir_all(root.join("build")).unwrap();
+        for (name, contents) in [
+            (".git/info/exclude", "excluded\n"),
+            (".gitignore", "*.log\n/build/\nsecret\n"),
+            ("sub/.gitignore", "!keep.log\n"),
+            ("sub/.ignore", "local\n"),
+            ("a.log", ""),
+            ("excluded", ""),
+            ("kept", ""),
+            ("local", ""),
+            ("secret", ""),
+            ("sub/a.log", ""),
+            ("sub/keep.log", ""),
+            ("sub/local", ""),
+            ("sub/secret", ""),
+            ("sub/build/out", ""),
+        ] {
+            fs::write(root.join(name), contents).unwrap();
+        }
+
+        let lister = Lister::new()
+            .path(root)
+            .tree(true)
+            .color(false)
+            .ignore_vcs(true);
+        let expected = format!(
+            concat!(
+                "{}\n",
+                "├── kept\n",
+                "├── local\n",
+                "└── sub/\n",
+                "    ├── build/\n",
+                "    │   └── out\n",
+                "    └── keep.log\n",
+                "\n",
+                "2 directories, 4 files\n",
+            ),
+            root.display()
+        );
+        assert_eq!(render(lister), expected);
+
+        // rules from above a listed subdirectory still apply
+        let out = render(
+            Lister::new()
+                .path(root.join("sub"))
+                .color(false)
+                .ignore_vcs(true),
+        );
+        assert_eq!(out, "build/  keep.log\n");
+    }
+}
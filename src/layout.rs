@@ -1,5 +1,4 @@
 use itertools::Itertools;
-use std::slice::ChunksExact;
 
 use crate::constants::*;
 
@@ -27,69 +26,148 @@ impl Default for LayoutInfo {
         }
     }
 }
-/// Calculates the column widths needed to accomodate all elements of `lens` in `num_cols`
-/// columns where elements of `lens` are placed down columns in order.
-fn col_widths_by_cols(min_width: usize, num_cols: usize, lens: &[usize]) -> Vec<usize> {
-    let num_rows = lens.len() / num_cols;
-    let rem = lens.len() % num_cols;
-    // the first `rem` cols must accomodate num_rows + 1 rows
-    let end = rem * (num_rows + 1);
+/// The column holding the element at `ind` of `num_lens` elements in a layout with `num_cols`
+/// columns. Elements placed across rows fill each row in turn, while elements placed down
+/// columns fill `num_lens.div_ceil(num_cols)` rows of each column in turn, like GNU `ls`.
+fn column(by_lines: bool, num_cols: usize, num_lens: usize, ind: usize) -> usize {
+    if by_lines {
+        ind % num_cols
+    } else {
+        ind / num_lens.div_ceil(num_cols)
+    }
+}
 
-    // calculate appropriate col width from chunks of columns
-    let chunks_to_col_width = |chunks: ChunksExact<_>| {
-        chunks
-            .into_iter()
-            .map(|col| col.iter().fold(min_width, |acc, l| std::cmp::max(acc, *l)))
-            .collect_vec()
-    };
+/// The width taken by an element of length `len` in column `col` of `num_cols` columns. Every
+/// column but the last is followed by `COL_SEP_LEN` spaces.
+fn cell_width(len: usize, col: usize, num_cols: usize) -> usize {
+    if col + 1 == num_cols {
+        len
+    } else {
+        len + COL_SEP_LEN
+    }
+}
 
-    // chunk the first rem columns by num_rows + 1 elements
-    let chunks = lens[..end].chunks_exact(num_rows + 1);
-    debug_assert!(chunks.remainder().is_empty());
-    let start_col_widths = chunks_to_col_width(chunks);
+/// The largest number of columns considered for `num_lens` elements, which is the number of
+/// `MIN_COL_SIZE` columns which start within `term_cols`.
+fn max_cols(term_cols: usize, num_lens: usize) -> usize {
+    std::cmp::min(std::cmp::max(term_cols.div_ceil(MIN_COL_SIZE), 1), num_lens)
+}
 
-    // chunk the rest of the columns by num_rows elements
-    let chunks = lens[end..].chunks_exact(num_rows);
-    debug_assert!(chunks.remainder().is_empty());
-    let fin_col_widths = chunks_to_col_width(chunks);
-    [start_col_widths, fin_col_widths].concat()
+/// Lower bound on the total width of any layout of `lens` in `num_cols` columns, given the
+/// sum and maximum of `lens`. Every column is at least `MIN_COL_SIZE` wide and one of them holds
+/// the longest element. A column is also at least as wide as the average of its elements, and no
+/// column holds more than `lens.len().div_ceil(num_cols)` elements.
+/// The bound never decreases as `num_cols` grows.
+fn min_total_width(num_cols: usize, num_lens: usize, sum_len: usize, max_len: usize) -> usize {
+    let by_max = std::cmp::max(max_len, MIN_COL_SIZE) + (num_cols - 1) * MIN_COL_SIZE;
+    let by_average = sum_len.div_ceil(num_lens.div_ceil(num_cols));
+    std::cmp::max(by_max, by_average)
 }
 
-/// Calculates the column widths needed to accomodate all elements of `lens` in `num_cols`
-/// columns where elements of `lens` are placed across rows in order.
-fn col_widths_by_lines(min_width: usize, num_cols: usize, lens: &[usize]) -> Vec<usize> {
-    let mut col_width = Vec::with_capacity(num_cols);
-    for offset in 0..num_cols {
-        let width = lens[offset..]
-            .iter()
-            .step_by(num_cols)
-            .fold(min_width, |acc, l| std::cmp::max(acc, *l));
-        col_width.push(width);
-    }
-    col_width
+/// The widths of the columns of a layout with a given number of columns, as they grow with each
+/// element placed in them.
+struct Candidate {
+    col_width: Vec<usize>,
+    total_width: usize,
+    /// whether the total width stayed below the terminal width each time a column grew
+    valid: bool,
 }
 
 /// Determines the layout for displaying a list of strings within the current terminal width with
-/// the maximal amount of columns. Each element of `lens` represents a string length that must fit
-/// within its assigned column.
+/// the maximal amount of columns, like GNU `ls`. Each element of `lens` represents a string length
+/// that must fit within its assigned column, and every column but the last is followed by
+/// `COL_SEP_LEN` spaces, which are included in its width.
 /// If `by_lines` is `true` the layout is determined by placing `lens`
 /// in order across rows. Otherwise, `lens` are placed down columns.
+///
+/// Like GNU `ls`, the widths of every candidate number of columns are tracked incrementally in a
+/// single pass over `lens`, dropping a candidate as soon as it becomes too wide. Candidates are
+/// first pruned by binary searching for the largest number of columns whose `min_total_width()`
+/// may fit, but the pass still takes O(n·k) time in the worst case for `k` remaining candidates.
 pub fn determine_layout(by_lines: bool, term_cols: usize, lens: &[usize]) -> LayoutInfo {
-    let max_cols = std::cmp::min(term_cols / MIN_COL_SIZE, lens.len());
+    let max_cols = max_cols(term_cols, lens.len());
+    if max_cols == 0 {
+        return LayoutInfo::default();
+    }
+    let sum_len = lens.iter().sum();
+    let max_len = lens.iter().copied().max().unwrap_or_default();
+    // a candidate whose bound is this wide has grown past its initial width, which is at most
+    // `term_cols + MIN_COL_SIZE - 1`, so it cannot be valid
+    let fits_bound = |num_cols| {
+        min_total_width(num_cols, lens.len(), sum_len, max_len) < term_cols + MIN_COL_SIZE
+    };
 
-    let mut valid_layouts = Vec::with_capacity(max_cols);
-    for num_cols in 1..=max_cols {
-        let col_width = if by_lines {
-            col_widths_by_lines(MIN_COL_SIZE, num_cols, lens)
+    // the largest column count which is not ruled out by `min_total_width()`
+    let (mut lo, mut hi) = (1, max_cols);
+    while lo < hi {
+        let mid = lo + (hi - lo).div_ceil(2);
+        if fits_bound(mid) {
+            lo = mid;
         } else {
-            col_widths_by_cols(MIN_COL_SIZE, num_cols, lens)
-        };
-        let total_width: usize = col_width.iter().sum();
-        if total_width <= term_cols {
-            valid_layouts.push(LayoutInfo::new(num_cols, col_width));
+            hi = mid - 1;
         }
     }
-    valid_layouts.pop().unwrap_or_default()
+
+    let mut candidates = (1..=lo)
+        .map(|num_cols| Candidate {
+            col_width: vec![MIN_COL_SIZE; num_cols],
+            total_width: num_cols * MIN_COL_SIZE,
+            valid: true,
+        })
+        .collect_vec();
+    for (ind, &len) in lens.iter().enumerate() {
+        for candidate in &mut candidates {
+            let num_cols = candidate.col_width.len();
+            let col = column(by_lines, num_cols, lens.len(), ind);
+            let width = cell_width(len, col, num_cols);
+            if width > candidate.col_width[col] {
+                candidate.total_width += width - candidate.col_width[col];
+                candidate.col_width[col] = width;
+                candidate.valid = candidate.total_width < term_cols;
+            }
+        }
+        // widths only ever grow, so a candidate which is too wide is dropped right away. The
+        // single column is the fallback, so its widths are kept up to date regardless
+        candidates.retain(|c| c.valid || c.col_width.len() == 1);
+    }
+    // a single column is used when nothing else fits, however wide it is
+    let col_width = candidates
+        .pop()
+        .expect("the single column is never dropped")
+        .col_width;
+    LayoutInfo::new(col_width.len(), col_width)
+}
+
+/// The original exhaustive search for the layout which `determine_layout()` chooses, computing
+/// the column widths for every possible number of columns. Kept as a reference for tests and
+/// benchmarks.
+#[cfg(any(test, feature = "bench"))]
+pub fn determine_layout_exhaustive(by_lines: bool, term_cols: usize, lens: &[usize]) -> LayoutInfo {
+    let max_cols = max_cols(term_cols, lens.len());
+    if max_cols == 0 {
+        return LayoutInfo::default();
+    }
+    let layouts = (1..=max_cols)
+        .map(|num_cols| {
+            let mut col_width = vec![MIN_COL_SIZE; num_cols];
+            for (ind, &len) in lens.iter().enumerate() {
+                let col = column(by_lines, num_cols, lens.len(), ind);
+                let width = cell_width(len, col, num_cols);
+                col_width[col] = std::cmp::max(col_width[col], width);
+            }
+            LayoutInfo::new(num_cols, col_width)
+        })
+        .collect_vec();
+    // a layout which never grew keeps its initial width, even if that does not fit
+    let valid = |layout: &LayoutInfo| {
+        let total_width: usize = layout.col_width.iter().sum();
+        total_width < term_cols || total_width == layout.num_cols * MIN_COL_SIZE
+    };
+    let ind = (1..layouts.len())
+        .rev()
+        .find(|&ind| valid(&layouts[ind]))
+        .unwrap_or(0);
+    layouts[ind].clone()
 }
 
 #[cfg(test)]
@@ -97,40 +175,61 @@ mod test {
     use super::*;
     #[test]
     fn test_determine_layout1() {
-        let term_cols = 10;
+        let term_cols = 13;
         let lens = vec![5, 5, 4, 4];
-        let layout_by_cols = LayoutInfo::new(2, vec![5, 4]);
+        // by columns, the first column is followed by the separator:
+        // aaaaa  cccc
+        // bbbbb  dddd
+        let layout_by_cols = LayoutInfo::new(2, vec![7, 4]);
         assert_eq!(determine_layout(false, term_cols, &lens), layout_by_cols);
-        let layout_by_lines = LayoutInfo::new(2, vec![5, 5]);
+        // aaaaa  bbbbb
+        // cccc   dddd
+        let layout_by_lines = LayoutInfo::new(2, vec![7, 5]);
         assert_eq!(determine_layout(true, term_cols, &lens), layout_by_lines);
+        // a layout must leave at least one cell of the terminal free
+        assert_eq!(
+            determine_layout(true, 12, &lens),
+            LayoutInfo::new(1, vec![5])
+        );
     }
 
     #[test]
     fn test_determine_layout2() {
-        let term_cols = 13;
+        let term_cols = 20;
         let lens = vec![3, 3, 7, 7, 3];
-        // by columns:
-        // attempting to layout this in 2 columns fails:
-        // the widths are:
-        // 3   7
-        // 3   3
-        // 7
-        // but three columns works:
-        // 3   7   3
-        // 3   7
-        let layout_by_cols = LayoutInfo::new(3, vec![3, 7, 3]);
+        // by columns, each column holds lens.len().div_ceil(num_cols) elements, so four columns
+        // would only fill three of them:
+        // aaa  ccccccc  eee
+        // bbb  ddddddd
+        let layout_by_cols = LayoutInfo::new(3, vec![5, 9, 3]);
         assert_eq!(determine_layout(false, term_cols, &lens), layout_by_cols);
-        // by lines:
-        // attempting to layout this in 2 columns fails:
-        // the widths are:
-        // 3   3
-        // 7   7
-        // 3
-        // three columns doesn't work either:
-        // 3   3   7
-        // 7   3
-        // only 1 column will work
-        let layout_by_lines = LayoutInfo::new(1, vec![7]);
+        // by lines, three columns are too wide:
+        // aaa      bbb  ccccccc
+        // ddddddd  eee
+        // but two columns fit:
+        // aaa      bbb
+        // ccccccc  ddddddd
+        // eee
+        let layout_by_lines = LayoutInfo::new(2, vec![9, 7]);
         assert_eq!(determine_layout(true, term_cols, &lens), layout_by_lines);
+        // a single column is used when nothing fits
+        assert_eq!(
+            determine_layout(false, 5, &lens),
+            LayoutInfo::new(1, vec![7])
+        );
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn test_determine_layout_matches_exhaustive(
+            by_lines: bool,
+            term_cols in 0usize..120,
+            lens in proptest::collection::vec(0usize..40, 0..200),
+        ) {
+            proptest::prop_assert_eq!(
+                determine_layout(by_lines, term_cols, &lens),
+                determine_layout_exhaustive(by_lines, term_cols, &lens)
+            );
+        }
     }
 }
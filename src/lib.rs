@@ -0,0 +1,43 @@
+//! Listing logic behind the `rusl` binary, an `ls` replacement.
+//!
+//! A [`Lister`] collects a [`Listing`] of structured entries for a set of paths and renders it
+//! into any [`std::io::Write`] using the same formats as the binary.
+
+pub mod acl;
+pub mod attrs;
+pub mod background;
+pub mod caps;
+pub mod config;
+pub mod constants;
+pub mod display;
+pub mod du;
+pub mod error;
+pub mod filemode;
+pub mod git;
+pub mod hyperlink;
+pub mod icons;
+mod ignore;
+pub mod layout;
+pub mod lister;
+pub mod pathinfo;
+mod pool;
+pub mod theme;
+#[cfg(feature = "io-uring")]
+pub mod uring;
+pub mod xattr;
+
+pub use acl::{Acl, AclEntry, AclTag, FileAcl};
+pub use attrs::{AttrFilter, InodeFlags};
+pub use background::Background;
+pub use caps::Capabilities;
+pub use display::DisplayOptions;
+pub use du::DuMode;
+pub use error::ListError;
+pub use filemode::{FileKind, FileMode};
+pub use git::{GitFileStatus, GitStatus};
+pub use icons::Icons;
+pub use layout::{LayoutInfo, determine_layout};
+pub use lister::{DirListing, Entry, Lister, Listing};
+pub use pathinfo::{LongPathInfo, Meta, PathInfo};
+pub use theme::{Color, ColorDepth, Style, Theme};
+pub use xattr::Xattr;
@@ -0,0 +1,720 @@
+use crate::acl::FileAcl;
+use crate::attrs::{AttrFilter, InodeFlags};
+use crate::caps::Capabilities;
+use crate::constants::*;
+use crate::display::{
+    DisplayOptions, display_dirs, display_paths, display_trees, needs_meta, write_pathinfo,
+};
+use crate::du::{DiskUsage, DuMode};
+use crate::error::ListError;
+#[cfg(feature = "git")]
+use crate::git::GitRepos;
+use crate::icons::Icons;
+use crate::ignore::DirIgnores;
+use crate::pathinfo::{Meta, PathInfo, lstat_at, stat};
+use crate::pool::for_each_parallel;
+use crate::theme::Theme;
+use crate::xattr;
+use itertools::Itertools;
+use nix::dir::Dir;
+use nix::fcntl::OFlag;
+use nix::sys::stat::Mode;
+use std::ffi::OsStr;
+use std::io::Result as IOResult;
+use std::io::{Error, Write};
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// A listed path along with the descendants collected beneath it. `children` is only
+/// populated for directories listed as a tree.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub info: PathInfo,
+    pub children: Vec<Entry>,
+}
+
+/// The entries collected for a directory operand.
+#[derive(Debug, Clone)]
+pub struct DirListing {
+    pub dir: PathInfo,
+    pub entries: Vec<Entry>,
+}
+
+/// Everything collected for the operands of a `Lister`.
+#[derive(Debug, Default)]
+pub struct Listing {
+    /// operands which are not directories, sorted
+    pub files: Vec<PathInfo>,
+    /// operands which are directories along with their contents, sorted
+    pub dirs: Vec<DirListing>,
+    /// errors encountered while collecting, in the order they occurred
+    pub errors: Vec<ListError>,
+}
+
+/// Builder for listing paths the way `rusl` does.
+///
+/// ```no_run
+/// use rusl::Lister;
+///
+/// let lister = Lister::new().path("src").long(true);
+/// let listing = lister.list();
+/// let mut out = Vec::new();
+/// lister.write(&listing, &mut out).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Lister {
+    paths: Vec<PathBuf>,
+    opts: DisplayOptions,
+    term_cols: usize,
+    jobs: usize,
+}
+
+impl Default for Lister {
+    fn default() -> Self {
+        Self {
+            paths: Vec::new(),
+            opts: DisplayOptions::default(),
+            term_cols: DEFAULT_TERM_COLS,
+            jobs: 1,
+        }
+    }
+}
+
+impl Lister {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a path to list. If no paths are added, the current directory is listed.
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.paths.push(path.into());
+        self
+    }
+
+    /// Add several paths to list.
+    pub fn paths<P: Into<PathBuf>>(mut self, paths: impl IntoIterator<Item = P>) -> Self {
+        self.paths.extend(paths.into_iter().map(Into::into));
+        self
+    }
+
+    /// Replace all display options at once.
+    pub fn options(mut self, opts: DisplayOptions) -> Self {
+        self.opts = opts;
+        self
+    }
+
+    /// Width of the terminal used to lay out columns.
+    pub fn term_cols(mut self, term_cols: usize) -> Self {
+        self.term_cols = term_cols;
+        self
+    }
+
+    /// Number of threads used to fetch metadata and walk directories, defaulting to 1.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = std::cmp::max(jobs, 1);
+        self
+    }
+
+    pub fn all(mut self, all: bool) -> Self {
+        self.opts.all = all;
+        self
+    }
+
+    pub fn long(mut self, long: bool) -> Self {
+        self.opts.long = long;
+        self
+    }
+
+    pub fn by_lines(mut self, by_lines: bool) -> Self {
+        self.opts.by_lines = by_lines;
+        self
+    }
+
+    pub fn tree(mut self, tree: bool) -> Self {
+        self.opts.tree = tree;
+        self
+    }
+
+    pub fn level(mut self, level: Option<usize>) -> Self {
+        self.opts.level = level;
+        self
+    }
+
+    pub fn dirs_first(mut self, dirs_first: bool) -> Self {
+        self.opts.dirs_first = dirs_first;
+        self
+    }
+
+    pub fn unsorted(mut self, unsorted: bool) -> Self {
+        self.opts.unsorted = unsorted;
+        self
+    }
+
+    pub fn one_per_line(mut self, one_per_line: bool) -> Self {
+        self.opts.one_per_line = one_per_line;
+        self
+    }
+
+    pub fn color(mut self, color: bool) -> Self {
+        self.opts.color = color;
+        self
+    }
+
+    pub fn theme(mut self, theme: Theme) -> Self {
+        self.opts.theme = theme;
+        self
+    }
+
+    pub fn du(mut self, du: Option<DuMode>) -> Self {
+        self.opts.du = du;
+        self
+    }
+
+    pub fn one_file_system(mut self, one_file_system: bool) -> Self {
+        self.opts.one_file_system = one_file_system;
+        self
+    }
+
+    pub fn sort_size(mut self, sort_size: bool) -> Self {
+        self.opts.sort_size = sort_size;
+        self
+    }
+
+    pub fn git(mut self, git: bool) -> Self {
+        self.opts.git = git;
+        self
+    }
+
+    pub fn git_ignore(mut self, git_ignore: bool) -> Self {
+        self.opts.git_ignore = git_ignore;
+        self
+    }
+
+    pub fn ignore_vcs(mut self, ignore_vcs: bool) -> Self {
+        self.opts.ignore_vcs = ignore_vcs;
+        self
+    }
+
+    pub fn icons(mut self, icons: Option<Icons>) -> Self {
+        self.opts.icons = icons;
+        self
+    }
+
+    pub fn xattrs(mut self, xattrs: bool) -> Self {
+        self.opts.xattrs = xattrs;
+        self
+    }
+
+    pub fn xattr_values(mut self, xattr_values: bool) -> Self {
+        self.opts.xattr_values = xattr_values;
+        self
+    }
+
+    pub fn acl(mut self, acl: bool) -> Self {
+        self.opts.acl = acl;
+        self
+    }
+
+    pub fn context(mut self, context: bool) -> Self {
+        self.opts.context = context;
+        self
+    }
+
+    pub fn caps(mut self, caps: bool) -> Self {
+        self.opts.caps = caps;
+        self
+    }
+
+    pub fn attrs(mut self, attrs: bool) -> Self {
+        self.opts.attrs = attrs;
+        self
+    }
+
+    pub fn attr_filter(mut self, attr_filter: Option<AttrFilter>) -> Self {
+        self.opts.attr_filter = attr_filter;
+        self
+    }
+
+    pub fn link_chain(mut self, link_chain: bool) -> Self {
+        self.opts.link_chain = link_chain;
+        self
+    }
+
+    pub fn hyperlink(mut self, hyperlink: bool) -> Self {
+        self.opts.hyperlink = hyperlink;
+        self
+    }
+
+    /// The display options currently configured.
+    pub fn display_options(&self) -> &DisplayOptions {
+        &self.opts
+    }
+
+    /// Collect the entries for every path without writing anything.
+    pub fn list(&self) -> Listing {
+        let mut listing = Listing::default();
+        let (dirs, mut files) = self.collect_operands(&mut listing.errors);
+        let annotations = Annotations::new(&self.opts);
+        annotations.apply(&self.opts, None, &mut files);
+        listing.files = sort_paths(&self.opts, files);
+        listing.dirs = dirs
+            .into_iter()
+            .map(|dir| {
+                let entries = self.collect_entries(
+                    None,
+                    &dir.path,
+                    1,
+                    self.jobs,
+                    &annotations,
+                    &mut listing.errors,
+                );
+                DirListing { dir, entries }
+            })
+            .collect();
+        listing
+    }
+
+    /// Write `listing` to `w` using the configured display options.
+    pub fn write(&self, listing: &Listing, w: &mut impl Write) -> IOResult<()> {
+        if !listing.files.is_empty() {
+            let files = listing.files.iter().collect_vec();
+            display_paths(w, &self.opts, self.term_cols, &files)?;
+        }
+
+        if !listing.dirs.is_empty() {
+            if self.opts.tree {
+                if !listing.files.is_empty() {
+                    writeln!(w)?;
+                }
+                display_trees(w, &self.opts, &listing.dirs)?;
+            } else {
+                display_dirs(w, &self.opts, self.term_cols, &listing.dirs)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Collect the entries for every path and write them to `w`, returning any errors
+    /// encountered while collecting. Entries are streamed if `streams()` is `true`.
+    pub fn render(&self, w: &mut impl Write) -> IOResult<Vec<ListError>> {
+        if self.streams() {
+            let mut errors = Vec::new();
+            self.stream(w, |err| errors.push(err))?;
+            return Ok(errors);
+        }
+        let listing = self.list();
+        self.write(&listing, w)?;
+        Ok(listing.errors)
+    }
+
+    /// Check if the entries of directories can be written as they are read instead of being
+    /// collected first, which is the case for unsorted listings with one entry per line.
+    pub fn streams(&self) -> bool {
+        self.opts.unsorted
+            && self.opts.one_per_line
+            && !self.opts.long
+            && !self.opts.tree
+            && !self.opts.dirs_first
+            && !self.opts.git
+            && !self.opts.git_ignore
+            && !self.opts.ignore_vcs
+            && !self.opts.context
+            && !self.opts.caps
+            && self.opts.attr_filter.is_none()
+    }
+
+    /// Write the entries of every path to `w` in directory order as they are read, calling
+    /// `on_error` for each error encountered. Entries are never collected, and are only stat-ed
+    /// when they are regular files which need to be coloured if they are executable.
+    ///
+    /// The output matches `write()` only when `streams()` is `true`.
+    pub fn stream(&self, w: &mut impl Write, mut on_error: impl FnMut(ListError)) -> IOResult<()> {
+        let mut errors = Vec::new();
+        let (dirs, files) = self.collect_operands(&mut errors);
+        errors.into_iter().for_each(&mut on_error);
+
+        if !files.is_empty() {
+            let files = files.iter().collect_vec();
+            display_paths(w, &self.opts, self.term_cols, &files)?;
+        }
+        for (ind, dir) in dirs.iter().enumerate() {
+            if ind > 0 {
+                writeln!(w)?;
+            }
+            if dirs.len() > 1 {
+                writeln!(w, "{}:", dir.path.display())?;
+            }
+            self.stream_dir(w, &dir.path, &mut on_error)?;
+        }
+        Ok(())
+    }
+
+    /// Write each child of `dir` on its own line as it is read. Metadata is only fetched when
+    /// needed to colour executables.
+    fn stream_dir(
+        &self,
+        w: &mut impl Write,
+        dir: &Path,
+        on_error: &mut impl FnMut(ListError),
+    ) -> IOResult<()> {
+        let entries = match dir.read_dir() {
+            Ok(entries) => entries,
+            Err(err) => {
+                on_error(ListError::Access {
+                    path: dir.to_path_buf(),
+                    err,
+                });
+                return Ok(());
+            }
+        };
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    on_error(ListError::ReadEntry { err });
+                    continue;
+                }
+            };
+            // filter out hidden paths if asked
+            if !self.opts.all && entry.file_name().as_encoded_bytes().starts_with(b".") {
+                continue;
+            }
+            match PathInfo::from_dir_entry(&entry) {
+                Ok(info) => {
+                    write_pathinfo(w, &self.opts, &info, 0)?;
+                    writeln!(w)?;
+                }
+                Err(err) => on_error(ListError::Access {
+                    path: entry.path(),
+                    err,
+                }),
+            }
+        }
+        Ok(())
+    }
+
+    /// The paths to list, defaulting to the current directory.
+    fn operands(&self) -> Vec<PathBuf> {
+        if self.paths.is_empty() {
+            vec![PathBuf::from(".")]
+        } else {
+            self.paths.clone()
+        }
+    }
+
+    /// Stat every operand and split them into directories and everything else, sorted unless
+    /// `opts.unsorted`.
+    fn collect_operands(&self, errors: &mut Vec<ListError>) -> (Vec<PathInfo>, Vec<PathInfo>) {
+        let mut pathsinfo = collect_pathinfo(&self.operands(), errors);
+        if !self.opts.unsorted {
+            pathsinfo.sort();
+        }
+        pathsinfo.into_iter().partition(|p| p.is_dir())
+    }
+
+    /// Collect the sorted children of `dir`, descending into subdirectories when listing as
+    /// a tree. `depth` is the depth of the children of `dir` below the listed directory, and
+    /// up to `jobs` threads are used to fetch metadata and walk subdirectories.
+    ///
+    /// Children are annotated and filtered using the state in `annotations`.
+    ///
+    /// `dir` is opened relative to `parent`, the already open directory containing it, if
+    /// given, and inherits the ignore rules in its scope with `opts.ignore_vcs`. Everything
+    /// below is opened and stat-ed relative to the file descriptor of its directory, so deep
+    /// trees are not limited by `PATH_MAX` and are not affected by their ancestors being moved
+    /// or replaced with symlinks while they are walked.
+    fn collect_entries(
+        &self,
+        parent: Option<Parent<'_>>,
+        dir: &Path,
+        depth: usize,
+        jobs: usize,
+        annotations: &Annotations,
+        errors: &mut Vec<ListError>,
+    ) -> Vec<Entry> {
+        let mut handle = match open_dir(parent.map(|p| p.fd), dir) {
+            Ok(handle) => handle,
+            Err(err) => {
+                errors.push(ListError::Access {
+                    path: dir.to_path_buf(),
+                    err,
+                });
+                return Vec::new();
+            }
+        };
+        let ignores = self
+            .opts
+            .ignore_vcs
+            .then(|| match parent.and_then(|p| p.ignores) {
+                Some(ignores) => {
+                    ignores.descend(dir.file_name().unwrap_or_default(), handle.as_fd())
+                }
+                None => DirIgnores::for_operand(dir, handle.as_fd()),
+            });
+        let mut children = recurse_dir(!self.opts.all, ignores.as_ref(), &mut handle, dir, errors);
+        let fd = handle.as_fd();
+        self.prefetch_meta(fd, jobs, &mut children);
+        annotations.apply(&self.opts, Some(fd), &mut children);
+        let children = sort_paths(&self.opts, children);
+
+        // symlinks to directories are not followed since `recurse_dir()` does not follow them
+        let descend = |info: &PathInfo| {
+            self.opts.tree && info.is_dir() && self.opts.level.is_none_or(|level| depth < level)
+        };
+        let mut walks = children
+            .into_iter()
+            .map(|info| {
+                let entry = Entry {
+                    info,
+                    children: Vec::new(),
+                };
+                (entry, Vec::new())
+            })
+            .collect_vec();
+        // with several subdirectories, walk them concurrently with a thread each rather than
+        // splitting the threads within each of them
+        let num_walks = walks
+            .iter()
+            .filter(|(entry, _)| descend(&entry.info))
+            .count();
+        let (walk_jobs, sub_jobs) = if num_walks > 1 { (jobs, 1) } else { (1, jobs) };
+        for_each_parallel(walk_jobs, 1, &mut walks, |(entry, errors)| {
+            if descend(&entry.info) {
+                let parent = Parent {
+                    fd,
+                    ignores: ignores.as_ref(),
+                };
+                entry.children = self.collect_entries(
+                    Some(parent),
+                    &entry.info.path,
+                    depth + 1,
+                    sub_jobs,
+                    annotations,
+                    errors,
+                );
+            }
+        });
+        // keep the errors in the order they would have occurred in a sequential walk
+        walks
+            .into_iter()
+            .map(|(entry, entry_errors)| {
+                errors.extend(entry_errors);
+                entry
+            })
+            .collect()
+    }
+
+    /// Fetch the metadata which displaying `paths` will need relative to `dir`, the directory
+    /// containing them, while it is still open. Large directories are fetched in a batch
+    /// through io_uring with the `io-uring` feature, or else using up to `jobs` threads.
+    fn prefetch_meta(&self, dir: BorrowedFd<'_>, jobs: usize, paths: &mut [PathInfo]) {
+        #[cfg(feature = "io-uring")]
+        if paths.len() >= URING_MIN_ENTRIES && crate::uring::prefetch_meta(&self.opts, dir, paths) {
+            return;
+        }
+        let fetch = |p: &mut PathInfo| {
+            if needs_meta(&self.opts, p) {
+                p.meta_at(dir);
+            }
+        };
+        // small directories are stat-ed on this thread since spawning more would cost more
+        // than it saves
+        if jobs > 1 && paths.len() >= PARALLEL_STAT_MIN_ENTRIES {
+            for_each_parallel(jobs, PARALLEL_STAT_CHUNK_SIZE, paths, fetch);
+        } else {
+            paths.iter_mut().for_each(fetch);
+        }
+    }
+}
+
+/// The already open directory containing a directory being collected.
+#[derive(Debug, Clone, Copy)]
+struct Parent<'a> {
+    fd: BorrowedFd<'a>,
+    /// the ignore rules in scope for the entries of the parent, with `opts.ignore_vcs`
+    ignores: Option<&'a DirIgnores>,
+}
+
+/// State shared by every directory of a listing for options which annotate or filter entries
+/// using more than their own metadata.
+#[derive(Debug, Default)]
+struct Annotations {
+    usage: Option<DiskUsage>,
+    #[cfg(feature = "git")]
+    git: Option<GitRepos>,
+}
+
+impl Annotations {
+    fn new(opts: &DisplayOptions) -> Self {
+        Self {
+            usage: opts
+                .du
+                .map(|mode| DiskUsage::new(mode, opts.one_file_system)),
+            #[cfg(feature = "git")]
+            git: (opts.git || opts.git_ignore).then(GitRepos::default),
+        }
+    }
+
+    /// Annotate `paths`, which are children of the open directory `dir`, or operands if it is
+    /// `None`. Children ignored by git are removed with `opts.git_ignore`, while operands are
+    /// always kept since they were asked for by name.
+    #[cfg_attr(not(feature = "git"), allow(unused_variables, clippy::ptr_arg))]
+    fn apply(&self, opts: &DisplayOptions, dir: Option<BorrowedFd<'_>>, paths: &mut Vec<PathInfo>) {
+        #[cfg(feature = "git")]
+        if let Some(git) = &self.git {
+            for p in paths.iter_mut() {
+                p.set_git_status(git.status(&p.path));
+            }
+            if opts.git_ignore && dir.is_some() {
+                paths.retain(|p| !p.git_status().is_some_and(|s| s.is_ignored()));
+            }
+        }
+        if opts.attrs || opts.attr_filter.is_some() {
+            for p in paths
+                .iter_mut()
+                .filter(|p| p.is_dir() || p.file_type().is_file())
+            {
+                p.set_inode_flags(InodeFlags::read(dir, &p.path));
+            }
+            if let Some(filter) = opts.attr_filter
+                && dir.is_some()
+            {
+                paths.retain(|p| filter.matches(p.inode_flags()));
+            }
+        }
+        if let Some(usage) = &self.usage {
+            usage.fill(dir, paths);
+        }
+        if opts.context {
+            for p in paths.iter_mut() {
+                p.set_security_context(xattr::security_context(dir, &p.path));
+            }
+        }
+        if opts.caps {
+            // only regular files can be executed with capabilities
+            for p in paths.iter_mut().filter(|p| p.file_type().is_file()) {
+                p.set_capabilities(Capabilities::read(dir, &p.path));
+            }
+        }
+        // the long format marks entries with ACLs, and shows extended attributes with `-@`
+        if opts.long {
+            for p in paths.iter_mut() {
+                p.set_acl(FileAcl::read(dir, &p.path).ok());
+                if opts.xattrs {
+                    let mut xattrs = xattr::list(dir, &p.path).unwrap_or_default();
+                    xattrs.sort_by(|a, b| a.name.cmp(&b.name));
+                    p.set_xattrs(xattrs);
+                }
+            }
+        }
+    }
+}
+
+/// Get metadata for a path, recording errors if any.
+fn stat_path(path: &Path, errors: &mut Vec<ListError>) -> Option<Meta> {
+    match stat(path) {
+        Ok(meta) => Some(meta),
+        Err(err) => {
+            errors.push(ListError::Access {
+                path: path.to_path_buf(),
+                err,
+            });
+            None
+        }
+    }
+}
+
+/// Attempt to create and collect `PathInfo` for each path in `paths`
+fn collect_pathinfo(paths: &[PathBuf], errors: &mut Vec<ListError>) -> Vec<PathInfo> {
+    paths
+        .iter()
+        .filter_map(|p| stat_path(p, errors).map(|meta| PathInfo::new(p.clone(), meta)))
+        .collect()
+}
+
+/// Open the directory `dir` for reading. Operands are opened by path following symlinks,
+/// while subdirectories are opened by name relative to `parent` without following them.
+pub(crate) fn open_dir(parent: Option<BorrowedFd<'_>>, dir: &Path) -> IOResult<Dir> {
+    let flags = OFlag::O_RDONLY | OFlag::O_DIRECTORY | OFlag::O_CLOEXEC;
+    let handle = match (parent, dir.file_name()) {
+        (Some(parent), Some(name)) => {
+            Dir::openat(parent, name, flags | OFlag::O_NOFOLLOW, Mode::empty())
+        }
+        _ => Dir::open(dir, flags, Mode::empty()),
+    };
+    handle.map_err(Error::from)
+}
+
+/// Collect `PathInfo` for the children of the open directory `handle` found at `dir`, optionally
+/// ignoring hidden files and those matched by `ignores`. Entries are read with `getdents64`
+/// through `readdir`, and are only stat-ed if the filesystem does not report their file type.
+fn recurse_dir(
+    ignore_hidden: bool,
+    ignores: Option<&DirIgnores>,
+    handle: &mut Dir,
+    dir: &Path,
+    errors: &mut Vec<ListError>,
+) -> Vec<PathInfo> {
+    let mut names = Vec::new();
+    for entry in handle.iter() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                errors.push(ListError::ReadEntry { err: err.into() });
+                continue;
+            }
+        };
+        let name = entry.file_name().to_bytes();
+        if name == b"." || name == b".." {
+            continue;
+        }
+        // filter out hidden paths if asked
+        if ignore_hidden && name.starts_with(b".") {
+            continue;
+        }
+        names.push((dir.join(OsStr::from_bytes(name)), entry.file_type()));
+    }
+
+    let fd = handle.as_fd();
+    let mut paths = Vec::with_capacity(names.len());
+    for (path, file_type) in names {
+        let name = path.file_name().unwrap_or_default();
+        let info = match file_type {
+            Some(file_type) => PathInfo::with_kind(path, file_type.into()),
+            None => match lstat_at(fd, Path::new(name)) {
+                Ok(meta) => PathInfo::new(path, meta),
+                Err(err) => {
+                    errors.push(ListError::Access { path, err });
+                    continue;
+                }
+            },
+        };
+        // filter out paths ignored by version control if asked, which needs the file type for
+        // patterns only matching directories
+        let name = info.path.file_name().unwrap_or_default();
+        if ignores.is_some_and(|ignores| ignores.is_ignored(name, info.is_dir())) {
+            continue;
+        }
+        paths.push(info);
+    }
+    paths
+}
+
+/// Sort `paths` by name unless `opts.unsorted`, largest first if `opts.sort_size`, then move
+/// directories ahead of everything else if `opts.dirs_first`. Sizes are the disk usage with
+/// `--du`.
+fn sort_paths(opts: &DisplayOptions, mut paths: Vec<PathInfo>) -> Vec<PathInfo> {
+    if !opts.unsorted {
+        paths.sort();
+        if opts.sort_size {
+            let size = |p: &PathInfo| p.usage().or_else(|| p.meta().map(|meta| meta.size));
+            paths.sort_by_key(|p| std::cmp::Reverse(size(p)));
+        }
+    }
+    if opts.dirs_first {
+        // the sort is stable, so each group keeps its ordering
+        paths.sort_by_key(|p| !p.is_dir());
+    }
+    paths
+}
@@ -1,29 +1,25 @@
-mod constants;
-mod filemode;
-mod layout;
-mod pathinfo;
-
-use crate::constants::*;
-use crate::filemode::FileMode;
-use crate::layout::{LayoutInfo, determine_layout};
-use crate::pathinfo::{LongPathInfo, PathInfo};
-use clap::ArgAction;
-use clap::Parser;
+use clap::error::ErrorKind as ClapErrorKind;
+use clap::{ArgAction, CommandFactory, Parser, ValueEnum};
 use itertools::Itertools;
-use std::fs;
-use std::fs::Metadata;
-use std::io::Error;
-use std::io::ErrorKind;
+use rusl::background;
+use rusl::config::{Config, OPTIONS_ENV};
+use rusl::constants::*;
+use rusl::display::DisplayOptions;
+use rusl::{AttrFilter, Background, ColorDepth, DuMode, Icons, Lister, Theme};
+use std::env;
+use std::ffi::OsString;
+use std::io;
 use std::io::Result as IOResult;
-use std::os::linux::fs::MetadataExt;
-use std::path::Path;
-use termion::color;
-use termion::style;
-use termion::terminal_size;
+use std::io::{BufWriter, ErrorKind, Write};
+use std::iter;
+use std::num::NonZero;
+use std::process;
+use std::thread;
+use termion::{is_tty, terminal_size};
 
 /// Command-line arguments for the program.
 #[derive(Debug, Parser)]
-#[command(disable_help_flag(true))]
+#[command(disable_help_flag(true), args_override_self(true))]
 struct Args {
     /// filepaths to process
     paths: Option<Vec<String>>,
@@ -40,336 +36,493 @@ struct Args {
     #[arg(short = 'x', default_value_t = false)]
     by_lines: bool,
 
+    /// list the contents of directories as a tree
+    #[arg(long, default_value_t = false)]
+    tree: bool,
+
+    /// descend at most N levels of directories when using --tree
+    #[arg(long, value_name = "N", requires = "tree")]
+    level: Option<usize>,
+
+    /// group directories before files
+    #[arg(long, default_value_t = false)]
+    dirs_first: bool,
+
+    /// do not sort; list entries in directory order
+    #[arg(short = 'U', default_value_t = false)]
+    unsorted: bool,
+
+    /// list one file per line
+    #[arg(short = '1', default_value_t = false)]
+    one_per_line: bool,
+
+    /// same as -a -U -1 without colours
+    #[arg(short = 'f', default_value_t = false)]
+    unsorted_all: bool,
+
+    /// colour entries and the fields of the long format with a theme file, given by name from
+    /// the themes directory of the config directory or by path
+    #[arg(long, value_name = "NAME")]
+    theme: Option<String>,
+
+    /// use the variant of the theme for a light or dark terminal background; `auto` asks the
+    /// terminal for its background colour when writing to it
+    #[arg(long, value_name = "WHEN", default_value = "auto")]
+    background: BackgroundArg,
+
+    /// show the recursive size of directories, counting the space allocated on disk or the
+    /// apparent size of their contents
+    #[arg(
+        long,
+        value_name = "MODE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "allocated"
+    )]
+    du: Option<DuArg>,
+
+    /// skip directories on other filesystems when using --du
+    #[arg(long, default_value_t = false, requires = "du")]
+    one_file_system: bool,
+
+    /// sort by size, largest first
+    #[arg(short = 'S', default_value_t = false)]
+    sort_size: bool,
+
+    /// show the git status of entries inside a work tree
+    #[arg(long, default_value_t = false)]
+    git: bool,
+
+    /// hide entries ignored by git
+    #[arg(long, default_value_t = false)]
+    git_ignore: bool,
+
+    /// hide entries matched by .gitignore, .ignore and git's exclude files
+    #[arg(long, default_value_t = false)]
+    ignore_vcs: bool,
+
+    /// show an icon before each entry, which needs a Nerd Font; `auto` shows them when
+    /// writing to a terminal. Set RUSL_ICONS to override the glyphs, e.g. `*.rs=R:di=D`
+    #[arg(
+        long,
+        value_name = "WHEN",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "auto"
+    )]
+    icons: Option<When>,
+
+    /// list the names and sizes of extended attributes below each entry in the long format
+    #[arg(short = '@', long, default_value_t = false)]
+    xattrs: bool,
+
+    /// show the values of extended attributes instead of their sizes
+    #[arg(long, default_value_t = false, requires = "xattrs")]
+    xattr_values: bool,
+
+    /// list the ACL entries of each entry below it in the long format, like getfacl
+    #[arg(long, default_value_t = false)]
+    acl: bool,
+
+    /// show the SELinux security context of each entry
+    #[arg(short = 'Z', long, default_value_t = false)]
+    context: bool,
+
+    /// show the file capabilities of each entry in the long format, and colour entries which
+    /// have any
+    #[arg(long, default_value_t = false)]
+    caps: bool,
+
+    /// show the inode flags of each entry in the long format, like lsattr
+    #[arg(long, default_value_t = false)]
+    attrs: bool,
+
+    /// only list entries with the given lsattr flags, or without those after a `-`, e.g.
+    /// `i-a` for immutable entries which are not append only
+    #[arg(long, value_name = "FLAGS", value_parser = AttrFilter::parse)]
+    attr_filter: Option<AttrFilter>,
+
+    /// show every symlink the target of a symlink goes through in the long format
+    #[arg(long, default_value_t = false)]
+    link_chain: bool,
+
+    /// link the name of each entry to its file, for terminals which support OSC 8 hyperlinks;
+    /// `auto` links them when writing to a terminal
+    #[arg(
+        long,
+        value_name = "WHEN",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "auto"
+    )]
+    hyperlink: Option<When>,
+
+    /// number of threads used to fetch metadata [default: number of CPUs]
+    #[arg(long, value_name = "N")]
+    jobs: Option<usize>,
+
+    /// add the options of a profile from the config file
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// ignore the config file and RUSL_OPTIONS
+    #[arg(long, default_value_t = false)]
+    no_config: bool,
+
+    #[command(flatten)]
+    negations: Negations,
+
     /// Print help
     #[arg(long, action = ArgAction::HelpShort)]
     help: Option<bool>,
 }
 
-/// Display options for formatting output.
-#[derive(Debug, Clone, PartialEq, Eq)]
-struct DisplayOptions {
-    /// show hidden paths
-    all: bool,
+/// Flags turning off options given earlier, so options set by the config file or
+/// `RUSL_OPTIONS` can be turned off on the command line. Hidden from the help to keep it short.
+#[derive(Debug, clap::Args)]
+struct Negations {
+    /// turn off -a/--all
+    #[arg(long, hide = true, overrides_with = "all")]
+    no_all: bool,
 
-    /// use long listing format
-    long: bool,
+    /// turn off -l
+    #[arg(long, hide = true, overrides_with = "long")]
+    no_long: bool,
 
-    /// display entries by lines instead of by columns
-    by_lines: bool,
-}
+    /// turn off -x
+    #[arg(long, hide = true, overrides_with = "by_lines")]
+    no_by_lines: bool,
 
-impl From<&Args> for DisplayOptions {
-    fn from(value: &Args) -> Self {
-        Self {
-            all: value.all,
-            long: value.long,
-            by_lines: value.by_lines,
-        }
-    }
+    /// turn off --tree
+    #[arg(long, hide = true, overrides_with_all = ["tree", "level"])]
+    no_tree: bool,
+
+    /// turn off --dirs-first
+    #[arg(long, hide = true, overrides_with = "dirs_first")]
+    no_dirs_first: bool,
+
+    /// turn off -U
+    #[arg(long, hide = true, overrides_with = "unsorted")]
+    no_unsorted: bool,
+
+    /// turn off -1
+    #[arg(long, hide = true, overrides_with = "one_per_line")]
+    no_one_per_line: bool,
+
+    /// turn off -f
+    #[arg(long, hide = true, overrides_with = "unsorted_all")]
+    no_unsorted_all: bool,
+
+    /// turn off --du
+    #[arg(long, hide = true, overrides_with_all = ["du", "one_file_system"])]
+    no_du: bool,
+
+    /// turn off --one-file-system
+    #[arg(long, hide = true, overrides_with = "one_file_system")]
+    no_one_file_system: bool,
+
+    /// turn off -S
+    #[arg(long, hide = true, overrides_with = "sort_size")]
+    no_sort_size: bool,
+
+    /// turn off --git
+    #[arg(long, hide = true, overrides_with = "git")]
+    no_git: bool,
+
+    /// turn off --git-ignore
+    #[arg(long, hide = true, overrides_with = "git_ignore")]
+    no_git_ignore: bool,
+
+    /// turn off --ignore-vcs
+    #[arg(long, hide = true, overrides_with = "ignore_vcs")]
+    no_ignore_vcs: bool,
+
+    /// turn off -@/--xattrs
+    #[arg(long, hide = true, overrides_with_all = ["xattrs", "xattr_values"])]
+    no_xattrs: bool,
+
+    /// turn off --xattr-values
+    #[arg(long, hide = true, overrides_with = "xattr_values")]
+    no_xattr_values: bool,
+
+    /// turn off --acl
+    #[arg(long, hide = true, overrides_with = "acl")]
+    no_acl: bool,
+
+    /// turn off -Z/--context
+    #[arg(long, hide = true, overrides_with = "context")]
+    no_context: bool,
+
+    /// turn off --caps
+    #[arg(long, hide = true, overrides_with = "caps")]
+    no_caps: bool,
+
+    /// turn off --attrs
+    #[arg(long, hide = true, overrides_with = "attrs")]
+    no_attrs: bool,
+
+    /// turn off --link-chain
+    #[arg(long, hide = true, overrides_with = "link_chain")]
+    no_link_chain: bool,
 }
 
-/// Format and print an error message.
-fn print_error_msg(what: &str, why: &str) {
-    eprintln!("{PROGRAM}: {what}: {why}");
+/// Values of options which can depend on whether stdout is a terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum When {
+    Auto,
+    Always,
+    Never,
 }
 
-/// Print `std::io::Error`s using predefined formats taken from ls errors.
-fn print_io_error(path: &Path, err: &Error) {
-    match err.kind() {
-        ErrorKind::NotFound => {
-            print_error_msg(
-                &format!("cannot access '{}'", path.display()),
-                ERR_NO_SUCH_FILE_OR_DIR,
-            );
-        }
-        ErrorKind::PermissionDenied => {
-            print_error_msg(
-                &format!("cannot access '{}'", path.display()),
-                ERR_PERM_DENIED,
-            );
-        }
-        _ => {
-            print_error_msg(
-                &format!("cannot access '{}'", path.display()),
-                "unknown error",
-            );
+impl When {
+    fn enabled(self) -> bool {
+        match self {
+            Self::Auto => is_tty(&io::stdout()),
+            Self::Always => true,
+            Self::Never => false,
         }
     }
 }
 
-/// Get metadata for a path, printing errors if any.
-fn stat_path(path: &Path) -> Option<fs::Metadata> {
-    match fs::metadata(path) {
-        Ok(meta) => Some(meta),
-        Err(err) => {
-            print_io_error(path, &err);
-            None
-        }
-    }
+/// Values of `--background`.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum BackgroundArg {
+    Auto,
+    Light,
+    Dark,
 }
 
-/// Attempt to create and collect `PathInfo` for each path in `paths`
-fn collect_pathinfo(paths: &[&Path]) -> Vec<PathInfo> {
-    paths
-        .iter()
-        .filter_map(|p| stat_path(p).map(|meta| PathInfo::new(p.to_path_buf(), meta)))
-        .collect()
+impl BackgroundArg {
+    /// The background of the terminal, detected with `auto` only when stdout is a terminal.
+    fn background(self) -> Option<Background> {
+        match self {
+            Self::Auto if is_tty(&io::stdout()) => background::detect(BACKGROUND_QUERY_TIMEOUT),
+            Self::Auto => None,
+            Self::Light => Some(Background::Light),
+            Self::Dark => Some(Background::Dark),
+        }
+    }
 }
 
-/// Check if a path is hidden (starts with '.').
-fn is_hidden(path: &Path) -> bool {
-    path.file_name()
-        .map(|s| s.to_string_lossy())
-        .is_some_and(|s| s.starts_with('.'))
+/// Values of `--du`.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum DuArg {
+    Apparent,
+    Allocated,
 }
 
-/// Recursively collect `PathInfo` for a directory, optionally ignoring hidden files.
-fn recurse_dir(ignore_hidden: bool, dir: &Path) -> Vec<PathInfo> {
-    match dir.read_dir() {
-        Ok(entries) => entries
-            .flat_map(|p| {
-                if let Err(err) = &p {
-                    print_error_msg("failed reading directory entry", &err.to_string());
-                }
-                p
-            })
-            .filter_map(|p| match p.metadata() {
-                Ok(meta) => {
-                    // filter out hidden paths if asked
-                    if ignore_hidden && is_hidden(&p.path()) {
-                        None
-                    } else {
-                        Some(PathInfo::new(p.path(), meta))
-                    }
-                }
-                Err(err) => {
-                    print_io_error(&p.path(), &err);
-                    None
-                }
-            })
-            .collect(),
-        Err(err) => {
-            print_io_error(dir, &err);
-            Vec::new()
+impl From<DuArg> for DuMode {
+    fn from(value: DuArg) -> Self {
+        match value {
+            DuArg::Apparent => Self::Apparent,
+            DuArg::Allocated => Self::Allocated,
         }
     }
 }
 
-/// Display `paths` using the long format for ls. The structure for the format is
-/// ```
-/// filetype_and_mode number_of_links file_owner file_group file_size last_modified file_name
-/// ```
-fn display_pathinfo_long(paths: &[PathInfo]) {
-    let longpaths = paths
-        .iter()
-        .map(|p| LongPathInfo::from(p.clone()))
-        .collect_vec();
-    let (
-        filetype_mode_width,
-        num_links_width,
-        file_owner_width,
-        file_group_width,
-        size_width,
-        last_modified_width,
-    ) = longpaths.iter().fold((0, 0, 0, 0, 0, 0), |acc, p| {
-        (
-            std::cmp::max(acc.0, p.filetype_mode.len()),
-            std::cmp::max(acc.1, p.num_links.len()),
-            std::cmp::max(acc.2, p.file_owner.len()),
-            std::cmp::max(acc.3, p.file_group.len()),
-            std::cmp::max(acc.4, p.size.len()),
-            std::cmp::max(acc.5, p.last_modified.len()),
-        )
-    });
-    for p in &longpaths {
-        // print all the fields with width and alignment
-        print!("{:filetype_mode_width$} ", p.filetype_mode);
-        print!("{:num_links_width$} ", p.num_links);
-        print!("{:file_owner_width$} ", p.file_owner);
-        print!("{:file_group_width$} ", p.file_group);
-        print!("{:size_width$} ", p.size);
-        print!("{:last_modified_width$} ", p.last_modified);
-        // file_name
-        print_pathinfo(&p.path, 0);
-        // optionally print link info
-        if p.path.meta.is_symlink() {
-            print!(" -> ");
-            let link_target = p.path.path.canonicalize().unwrap_or_default();
-            print!(
-                "{}{}{}{}",
-                style::Bold,
-                color::Fg(color::Green),
-                link_target.display(),
-                style::Reset,
-            );
+impl From<&Args> for DisplayOptions {
+    fn from(value: &Args) -> Self {
+        Self {
+            all: value.all || value.unsorted_all,
+            long: value.long,
+            by_lines: value.by_lines,
+            tree: value.tree,
+            level: value.level,
+            dirs_first: value.dirs_first,
+            unsorted: value.unsorted || value.unsorted_all,
+            one_per_line: value.one_per_line || value.unsorted_all,
+            color: !value.unsorted_all,
+            theme: Theme::default(),
+            du: value.du.map(DuMode::from),
+            one_file_system: value.one_file_system,
+            sort_size: value.sort_size,
+            git: value.git,
+            git_ignore: value.git_ignore,
+            ignore_vcs: value.ignore_vcs,
+            icons: value.icons.filter(|when| when.enabled()).map(|_| {
+                let overrides = env::var("RUSL_ICONS").unwrap_or_default();
+                Icons::default().with_overrides(&overrides)
+            }),
+            xattrs: value.xattrs,
+            xattr_values: value.xattr_values,
+            acl: value.acl,
+            context: value.context,
+            caps: value.caps,
+            attrs: value.attrs,
+            attr_filter: value.attr_filter,
+            link_chain: value.link_chain,
+            hyperlink: value.hyperlink.is_some_and(When::enabled),
         }
-        println!();
     }
 }
 
-/// Determine a layout for the `paths` based on `term_cols` and display them
-fn display_paths(opts: &DisplayOptions, term_cols: usize, paths: &[PathInfo]) {
-    let lens = paths
-        .iter()
-        .map(|p| p.to_string().len() + COL_SEP_LEN)
-        .collect_vec();
-    let layout = determine_layout(opts.by_lines, term_cols, &lens);
-    if opts.long {
-        display_pathinfo_long(paths);
-    } else if opts.by_lines {
-        display_by_lines(&layout, paths);
-    } else {
-        display_by_cols(&layout, paths);
-    }
+/// Determine the width used to lay out columns: `COLUMNS` if it is set, then the width of the
+/// terminal, falling back to `DEFAULT_TERM_COLS` when stdout is not a terminal.
+fn term_cols() -> usize {
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|cols| cols.parse().ok())
+        .filter(|&cols| cols > 0)
+        .or_else(|| terminal_size().ok().map(|(cols, _)| cols as usize))
+        .unwrap_or(DEFAULT_TERM_COLS)
 }
 
-/// Display `paths` ascending down columns using the number of columns and
-/// column widths specified by `layout`
-fn display_by_cols(layout: &LayoutInfo, paths: &[PathInfo]) {
-    let num_cols = layout.num_cols;
-    // the first num_rows rows will be full
-    let num_rows = paths.len() / num_cols;
-    // the final row will consist of rem columns
-    let rem = paths.len() % num_cols;
-    // dbg!(num_rows, rem, str_paths.len(), str_paths);
-    // print the full rows
-    for r in 0..num_rows {
-        let skip = num_rows + 1;
-        let end = r + skip * rem;
-        let strs = paths[r..end].iter().step_by(skip);
-        for (c, p) in strs.enumerate() {
-            print_pathinfo(p, layout.col_width[c]);
-        }
-        let skip = num_rows;
-        let strs = paths[end..].iter().step_by(skip);
-        for (c, p) in strs.enumerate() {
-            // we've already done the first rem columns
-            print_pathinfo(p, layout.col_width[c + rem]);
-        }
-        println!();
-    }
-    if rem > 0 {
-        // print the final partial row
-        let skip = num_rows + 1;
-        let strs = paths[num_rows..].iter().step_by(skip);
-        for (c, p) in strs.enumerate() {
-            print_pathinfo(p, layout.col_width[c]);
+/// Load the theme file `name` if it is given, falling back to the default theme, using its
+/// variant for `background`. Its colours are downgraded to those the terminal supports
+/// according to `COLORTERM` and `TERM`.
+fn load_theme(name: Option<&str>, background: Option<Background>) -> Theme {
+    let theme = match name.map(|name| (name, Theme::path(name))) {
+        None => Theme::builtin(background),
+        Some((_, Some(path))) => Theme::load(&path, background).unwrap_or_else(|err| {
+            eprintln!("{PROGRAM}: {err}");
+            process::exit(2);
+        }),
+        Some((name, None)) => {
+            eprintln!("{PROGRAM}: cannot find theme '{name}' without a config directory");
+            process::exit(2);
         }
-    }
-}
-
-/// Check if `meta` represents an executable file (i.e., any execute bit set)
-fn is_executable(meta: &Metadata) -> bool {
-    let mode = FileMode(meta.st_mode());
-    mode.user_execute() || mode.group_execute() || mode.other_execute()
+    };
+    let colorterm = env::var("COLORTERM").ok();
+    let term = env::var("TERM").ok();
+    theme.downgrade(ColorDepth::detect(colorterm.as_deref(), term.as_deref()))
 }
 
-/// Print `path` using ls-like colors according to the file type.
-/// Add whitespace after the path to fill `col_width` characters.
-fn print_pathinfo(path: &PathInfo, col_width: usize) {
-    let s = path.to_string();
-    // when there is only 1 column, it is possible that the width does not accomodate the
-    // the string
-    let indent_len = col_width.saturating_sub(s.len());
-    if path.meta.is_dir() {
-        print!(
-            "{}{}{}{}{}",
-            style::Bold,
-            color::Fg(color::Blue),
-            s,
-            style::Reset,
-            " ".repeat(indent_len)
-        );
-    } else if path.meta.is_symlink() {
-        print!(
-            "{}{}{}{}{}",
-            style::Bold,
-            color::Fg(color::Cyan),
-            s,
-            style::Reset,
-            " ".repeat(indent_len)
-        );
-    } else if is_executable(&path.meta) {
-        print!(
-            "{}{}{}{}{}",
-            style::Bold,
-            color::Fg(color::Green),
-            s,
-            style::Reset,
-            " ".repeat(indent_len)
-        );
-    } else {
-        print!("{}{}", s, " ".repeat(indent_len));
+/// The message of a clap error on one line, without its usage and hints.
+fn clap_message(err: &clap::Error) -> String {
+    if !err.use_stderr() {
+        return "--help cannot be set as a default option".to_string();
     }
+    let msg = err.to_string();
+    let msg = msg
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .map(str::trim)
+        .join(" ");
+    msg.strip_prefix("error: ").unwrap_or(&msg).to_string()
 }
 
-/// Display `paths` ascending across rows using the number of columns and
-/// column widths specified by `layout`
-fn display_by_lines(layout: &LayoutInfo, paths: &[PathInfo]) {
-    let chunks = paths.chunks(layout.num_cols);
-    for chunk in chunks {
-        for (ind, p) in chunk.iter().enumerate() {
-            print_pathinfo(p, layout.col_width[ind]);
-        }
-        println!();
+/// Check the default `options` set in `source` on their own, so mistakes in them are not
+/// reported as if they were on the command line. Exits if they do not parse or contain paths.
+/// Options which need another one, like `--level` without `--tree`, are only checked once
+/// every source is combined, since another source may give it.
+fn check_options(program: &OsString, source: &str, options: &[String]) {
+    let args = iter::once(program.clone())
+        .chain(options.iter().map(OsString::from))
+        .collect_vec();
+    let matches = Args::command()
+        .try_get_matches_from(&args)
+        .or_else(|err| match err.kind() {
+            ClapErrorKind::MissingRequiredArgument => Args::command()
+                .ignore_errors(true)
+                .try_get_matches_from(&args),
+            _ => Err(err),
+        });
+    let msg = match matches {
+        Ok(matches) => matches
+            .get_many::<String>("paths")
+            .and_then(|mut paths| paths.next())
+            .map(|path| format!("unexpected path '{path}', only options can be set")),
+        Err(err) => Some(clap_message(&err)),
+    };
+    if let Some(msg) = msg {
+        eprintln!("{PROGRAM}: {source}: {msg}");
+        process::exit(2);
     }
 }
 
-/// Collect and print the children of `dir` using `recurse_dir()`.
-/// Optionally, include a total size if `opts.long`
-/// is `true` and skip hidden children if `opts.all` is `false`.
-fn display_dir_contents(opts: &DisplayOptions, term_cols: usize, dir: &PathInfo) {
-    if opts.long {
-        println!("total {}", dir.meta.st_size());
+/// Parse the command line, preceded by the default options from the config file and
+/// `RUSL_OPTIONS` unless `--no-config` is given. Options given later override earlier ones, so
+/// the command line takes precedence over the defaults, and boolean options set by default can
+/// be turned off with `--no-` followed by their name, e.g. `--no-all` or `--no-long`.
+fn parse_args() -> Args {
+    let cli = env::args_os().collect_vec();
+    // only `--no-config` and `--profile` are needed before the defaults are known, and options
+    // needing another one, like `--level` without `--tree`, may be completed by the defaults
+    let matches = Args::command()
+        .try_get_matches_from(&cli)
+        .or_else(|err| match err.kind() {
+            ClapErrorKind::MissingRequiredArgument => Args::command()
+                .ignore_errors(true)
+                .try_get_matches_from(&cli),
+            _ => Err(err),
+        })
+        .unwrap_or_else(|err| err.exit());
+    let profile = matches.get_one::<String>("profile").cloned();
+    if matches.get_flag("no_config") {
+        return Args::parse_from(&cli);
     }
-    let children: Vec<_> = recurse_dir(!opts.all, &dir.path)
-        .into_iter()
-        .sorted()
-        .collect();
-    display_paths(opts, term_cols, &children);
-}
-/// Iterate over all directories in `dirs`, displaying each.
-/// If there is more than one directory, preface the directory contents with
-/// the directory name.
-fn display_dirs(opts: &DisplayOptions, term_cols: usize, dirs: &[PathInfo]) {
-    // print files from dirs grouped if there are more than one
-    if dirs.len() > 1 {
-        println!("{}:", dirs[0].path.display());
+    let config = match Config::default_path().map(|path| Config::load(&path)) {
+        Some(Ok(config)) => config,
+        Some(Err(err)) => {
+            eprintln!("{PROGRAM}: {err}");
+            Config::default()
+        }
+        None => Config::default(),
+    };
+    let env_options = env::var(OPTIONS_ENV).ok();
+    let cwd = env::current_dir().unwrap_or_default();
+    let sources = match config.options(&cwd, env_options.as_deref(), profile.as_deref()) {
+        Ok(sources) => sources,
+        Err(err) => {
+            eprintln!("{PROGRAM}: {err}");
+            process::exit(2);
+        }
+    };
+    if sources.is_empty() {
+        return Args::parse_from(&cli);
     }
-    for (ind, dir) in dirs[..dirs.len() - 1].iter().enumerate() {
-        display_dir_contents(opts, term_cols, dir);
-        println!();
-        println!("{}:", dirs[ind + 1].path.display())
+    let (program, cli) = cli.split_first().expect("the program name is always given");
+    for source in &sources {
+        check_options(program, &source.source, &source.options);
     }
-    // print the final dir
-    display_dir_contents(opts, term_cols, &dirs[dirs.len() - 1]);
+    let names = sources.iter().map(|source| &source.source).join(", ");
+    let args = iter::once(program.clone())
+        .chain(
+            sources
+                .into_iter()
+                .flat_map(|source| source.options)
+                .map(OsString::from),
+        )
+        .chain(cli.iter().cloned());
+    Args::try_parse_from(args).unwrap_or_else(|err| {
+        let msg = clap_message(&err);
+        eprintln!("{PROGRAM}: {msg} (with the default options from {names})");
+        process::exit(2);
+    })
 }
 
 fn main() -> IOResult<()> {
-    let args = Args::parse();
-    let opts = DisplayOptions::from(&args);
+    let args = parse_args();
+    let mut opts = DisplayOptions::from(&args);
+    if opts.color {
+        opts.theme = load_theme(args.theme.as_deref(), args.background.background());
+    }
+    if cfg!(not(feature = "git")) && (opts.git || opts.git_ignore) {
+        eprintln!("{PROGRAM}: built without git support, ignoring --git and --git-ignore");
+    }
+    let jobs = args
+        .jobs
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZero::get));
+
+    let lister = Lister::new()
+        .paths(args.paths.unwrap_or_default())
+        .options(opts)
+        .term_cols(term_cols())
+        .jobs(jobs);
 
-    // default to checking the cwd
-    let string_paths = if args.paths.is_none() {
-        vec![".".to_string()]
+    let mut out = BufWriter::new(io::stdout().lock());
+    let res = if lister.streams() {
+        lister.stream(&mut out, |err| eprintln!("{PROGRAM}: {err}"))
     } else {
-        args.paths.unwrap()
+        let listing = lister.list();
+        for err in &listing.errors {
+            eprintln!("{PROGRAM}: {err}");
+        }
+        lister.write(&listing, &mut out)
     };
-
-    let paths = string_paths.iter().map(Path::new).collect_vec();
-
-    let pathsinfo = collect_pathinfo(&paths).into_iter().sorted();
-
-    let (dirs, files): (Vec<_>, Vec<_>) = pathsinfo.partition(|p| p.meta.is_dir());
-
-    let (term_cols, _) = terminal_size()?;
-    let term_cols = term_cols as usize;
-    if !files.is_empty() {
-        display_paths(&opts, term_cols, &files);
-    }
-
-    if !dirs.is_empty() {
-        display_dirs(&opts, term_cols, &dirs);
+    match res.and_then(|_| out.flush()) {
+        // the reader went away (e.g. piped into `head`), so there is nobody left to tell
+        Err(err) if err.kind() == ErrorKind::BrokenPipe => Ok(()),
+        res => res,
     }
-    Ok(())
 }
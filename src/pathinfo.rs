@@ -1,19 +1,148 @@
-use crate::filemode::FileMode;
+use crate::acl::FileAcl;
+use crate::attrs::InodeFlags;
+use crate::caps::Capabilities;
+use crate::filemode::{FileKind, FileMode};
+use crate::git::GitFileStatus;
+use crate::xattr::Xattr;
+use nix::fcntl::AtFlags;
+use nix::sys::stat::{FileStat, fstatat};
 use nix::unistd::{Gid, Group, Uid, User};
+use std::cell::OnceCell;
 use std::fmt;
 use std::fmt::Display;
 use std::fs;
+use std::fs::DirEntry;
+use std::io::Result as IOResult;
+use std::os::fd::BorrowedFd;
 use std::os::linux::fs::MetadataExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, SystemTime};
 
-/// Holds both the path to a file and associated metadata.
+#[cfg(debug_assertions)]
+thread_local! {
+    static STAT_CALLS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Number of stat calls made through this module by the current thread. Only tracked in debug
+/// builds, so tests can assert that listings avoid unnecessary syscalls.
+#[cfg(debug_assertions)]
+pub fn stat_calls() -> usize {
+    STAT_CALLS.with(|calls| calls.get())
+}
+
+/// Record a stat call in debug builds.
+pub(crate) fn count_stat() {
+    #[cfg(debug_assertions)]
+    STAT_CALLS.with(|calls| calls.set(calls.get() + 1));
+}
+
+/// `fs::metadata()`, counted by `stat_calls()`.
+pub(crate) fn stat(path: &Path) -> IOResult<Meta> {
+    count_stat();
+    fs::metadata(path).map(|meta| Meta::from(&meta))
+}
+
+/// `fs::symlink_metadata()`, counted by `stat_calls()`.
+pub(crate) fn lstat(path: &Path) -> IOResult<Meta> {
+    count_stat();
+    fs::symlink_metadata(path).map(|meta| Meta::from(&meta))
+}
+
+/// `fstatat()` of `name` relative to the directory `dir` without following symlinks, counted by
+/// `stat_calls()`.
+pub(crate) fn lstat_at(dir: BorrowedFd<'_>, name: &Path) -> IOResult<Meta> {
+    count_stat();
+    Ok(Meta::from(&fstatat(
+        dir,
+        name,
+        AtFlags::AT_SYMLINK_NOFOLLOW,
+    )?))
+}
+
+/// The metadata of a file which is used for listing it. Unlike `fs::Metadata`, this can be
+/// filled in from the buffers returned by any of the stat syscalls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Meta {
+    /// file type and mode bits
+    pub mode: u32,
+    pub nlink: u64,
+    pub uid: u32,
+    pub gid: u32,
+    /// size in bytes
+    pub size: u64,
+    /// number of 512 byte blocks allocated
+    pub blocks: u64,
+    /// last modification time in seconds since the epoch
+    pub mtime: i64,
+    pub dev: u64,
+    pub ino: u64,
+}
+
+impl Meta {
+    pub fn kind(&self) -> FileKind {
+        FileKind::from_mode(self.mode)
+    }
+}
+
+impl From<&fs::Metadata> for Meta {
+    fn from(meta: &fs::Metadata) -> Self {
+        Self {
+            mode: meta.st_mode(),
+            nlink: meta.st_nlink(),
+            uid: meta.st_uid(),
+            gid: meta.st_gid(),
+            size: meta.st_size(),
+            blocks: meta.st_blocks(),
+            mtime: meta.st_mtime(),
+            dev: meta.st_dev(),
+            ino: meta.st_ino(),
+        }
+    }
+}
+
+impl From<&FileStat> for Meta {
+    fn from(stat: &FileStat) -> Self {
+        Self {
+            mode: stat.st_mode,
+            // `nlink_t` is narrower than `u64` on some targets
+            #[allow(clippy::unnecessary_cast)]
+            nlink: stat.st_nlink as u64,
+            uid: stat.st_uid,
+            gid: stat.st_gid,
+            size: stat.st_size as u64,
+            blocks: stat.st_blocks as u64,
+            mtime: stat.st_mtime,
+            dev: stat.st_dev,
+            ino: stat.st_ino,
+        }
+    }
+}
+
+/// Holds both the path to a file and associated metadata. The metadata of paths read from a
+/// directory is only fetched the first time it is needed, since their file type is usually
+/// known without it.
 #[derive(Debug, Clone)]
 pub struct PathInfo {
     /// file path
     pub path: PathBuf,
-    /// metadata associated with path
-    pub meta: fs::Metadata,
+    /// file type of path, which does not follow symlinks unless path was stat-ed eagerly
+    file_type: FileKind,
+    /// metadata associated with path, or `None` if it could not be fetched
+    meta: OnceCell<Option<Meta>>,
+    /// recursive disk usage, only computed with `--du`
+    usage: Option<u64>,
+    /// git status, only computed with `--git`
+    git_status: Option<GitFileStatus>,
+    /// SELinux security context, only read with `-Z`
+    security_context: Option<String>,
+    /// file capabilities, only read with `--caps`
+    capabilities: Option<Capabilities>,
+    /// inode flags, only read with `--attrs` or `--attr-filter`
+    inode_flags: Option<InodeFlags>,
+    /// POSIX ACLs, only read in the long format
+    acl: Option<FileAcl>,
+    /// extended attributes sorted by name, only read with `-@`
+    xattrs: Vec<Xattr>,
 }
 
 // an alternative to defining these on the field that matters
@@ -45,7 +174,7 @@ impl Display for PathInfo {
             .map(|s| s.to_string_lossy())
             .map(|s| s.to_string())
             .unwrap_or_default();
-        if self.meta.is_dir() {
+        if self.is_dir() {
             s.push('/');
         }
         write!(f, "{}", s)
@@ -53,8 +182,153 @@ impl Display for PathInfo {
 }
 
 impl PathInfo {
-    pub fn new(path: PathBuf, meta: fs::Metadata) -> Self {
-        Self { path, meta }
+    pub fn new(path: PathBuf, meta: Meta) -> Self {
+        Self {
+            path,
+            file_type: meta.kind(),
+            meta: OnceCell::from(Some(meta)),
+            usage: None,
+            git_status: None,
+            security_context: None,
+            capabilities: None,
+            inode_flags: None,
+            acl: None,
+            xattrs: Vec::new(),
+        }
+    }
+
+    /// Create a `PathInfo` for a directory entry without fetching its metadata. The file type
+    /// comes from the directory entry itself on filesystems which report it.
+    pub fn from_dir_entry(entry: &DirEntry) -> IOResult<Self> {
+        Ok(Self {
+            path: entry.path(),
+            file_type: entry.file_type()?.into(),
+            meta: OnceCell::new(),
+            usage: None,
+            git_status: None,
+            security_context: None,
+            capabilities: None,
+            inode_flags: None,
+            acl: None,
+            xattrs: Vec::new(),
+        })
+    }
+
+    /// Create a `PathInfo` for a path whose file type is already known, without fetching its
+    /// metadata.
+    pub(crate) fn with_kind(path: PathBuf, file_type: FileKind) -> Self {
+        Self {
+            path,
+            file_type,
+            meta: OnceCell::new(),
+            usage: None,
+            git_status: None,
+            security_context: None,
+            capabilities: None,
+            inode_flags: None,
+            acl: None,
+            xattrs: Vec::new(),
+        }
+    }
+
+    pub fn file_type(&self) -> FileKind {
+        self.file_type
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type.is_dir()
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.file_type.is_symlink()
+    }
+
+    /// The metadata of the path, which is fetched without following symlinks the first time
+    /// this is called. Returns `None` if it could not be fetched, e.g. if the path was removed.
+    pub fn meta(&self) -> Option<&Meta> {
+        self.meta.get_or_init(|| lstat(&self.path).ok()).as_ref()
+    }
+
+    /// Like `meta()`, but fetches the metadata relative to `dir`, which must be the directory
+    /// containing the path. This avoids resolving the whole path again, so it works for paths
+    /// longer than `PATH_MAX` and cannot be redirected by a parent directory being replaced.
+    pub(crate) fn meta_at(&self, dir: BorrowedFd<'_>) -> Option<&Meta> {
+        self.meta
+            .get_or_init(|| lstat_at(dir, Path::new(self.path.file_name()?)).ok())
+            .as_ref()
+    }
+
+    /// The size of the path counting everything below it if it is a directory, if it was
+    /// computed for `--du`.
+    pub fn usage(&self) -> Option<u64> {
+        self.usage
+    }
+
+    pub(crate) fn set_usage(&mut self, usage: u64) {
+        self.usage = Some(usage);
+    }
+
+    /// The git status of the path, if it was computed for `--git` and the path is inside a
+    /// work tree.
+    pub fn git_status(&self) -> Option<GitFileStatus> {
+        self.git_status
+    }
+
+    #[cfg(feature = "git")]
+    pub(crate) fn set_git_status(&mut self, git_status: Option<GitFileStatus>) {
+        self.git_status = git_status;
+    }
+
+    /// The SELinux security context of the path, if it was read for `-Z` and it has one.
+    pub fn security_context(&self) -> Option<&str> {
+        self.security_context.as_deref()
+    }
+
+    pub(crate) fn set_security_context(&mut self, security_context: Option<String>) {
+        self.security_context = security_context;
+    }
+
+    /// The file capabilities of the path, if they were read for `--caps` and it has any.
+    pub fn capabilities(&self) -> Option<&Capabilities> {
+        self.capabilities.as_ref()
+    }
+
+    pub(crate) fn set_capabilities(&mut self, capabilities: Option<Capabilities>) {
+        self.capabilities = capabilities;
+    }
+
+    /// The inode flags of the path, if they were read for `--attrs` or `--attr-filter` and its
+    /// filesystem supports them.
+    pub fn inode_flags(&self) -> Option<InodeFlags> {
+        self.inode_flags
+    }
+
+    pub(crate) fn set_inode_flags(&mut self, inode_flags: Option<InodeFlags>) {
+        self.inode_flags = inode_flags;
+    }
+
+    /// The ACLs of the path, if they were read for the long format.
+    pub fn acl(&self) -> Option<&FileAcl> {
+        self.acl.as_ref()
+    }
+
+    pub(crate) fn set_acl(&mut self, acl: Option<FileAcl>) {
+        self.acl = acl;
+    }
+
+    /// The extended attributes of the path sorted by name, if they were read for `-@`.
+    pub fn xattrs(&self) -> &[Xattr] {
+        &self.xattrs
+    }
+
+    pub(crate) fn set_xattrs(&mut self, xattrs: Vec<Xattr>) {
+        self.xattrs = xattrs;
+    }
+
+    /// Fill in metadata fetched elsewhere, e.g. in a batch with other paths. `None` records
+    /// that it could not be fetched. Does nothing if the metadata is already known.
+    pub fn set_meta(&mut self, meta: Option<Meta>) {
+        let _ = self.meta.set(meta);
     }
 }
 
@@ -64,6 +338,12 @@ pub struct LongPathInfo {
     pub num_links: String,
     pub file_owner: String,
     pub file_group: String,
+    /// SELinux security context, or `?` if it has none or was not read
+    pub security_context: String,
+    /// file capabilities like `getcap` shows them, or `-` if it has none or they were not read
+    pub capabilities: String,
+    /// inode flags like `lsattr` shows them, or `?` if they are unknown or were not read
+    pub inode_flags: String,
     pub size: String,
     pub last_modified: String,
     pub path: PathInfo,
@@ -72,32 +352,48 @@ pub struct LongPathInfo {
 /// Collects `ls` long output metadata from `PathInfo` and produce a `LongPathInfo`
 impl From<PathInfo> for LongPathInfo {
     fn from(p: PathInfo) -> Self {
-        // filetype and mode
-        let filetype = if p.meta.is_dir() {
-            "d"
-        } else if p.meta.is_symlink() {
-            "l"
-        } else {
-            "-"
+        let filetype = p.file_type().symbol();
+        // like ls, show question marks for every field which could not be determined
+        let security_context = p.security_context().unwrap_or("?").to_string();
+        let capabilities = p
+            .capabilities()
+            .map_or_else(|| "-".to_string(), ToString::to_string);
+        let inode_flags = p
+            .inode_flags()
+            .map_or_else(|| "?".to_string(), |flags| flags.to_string());
+        let Some(meta) = p.meta().copied() else {
+            let unknown = "?".to_string();
+            return Self {
+                filetype_mode: format!("{filetype}?????????"),
+                num_links: unknown.clone(),
+                file_owner: unknown.clone(),
+                file_group: unknown.clone(),
+                security_context,
+                capabilities,
+                inode_flags,
+                size: unknown.clone(),
+                last_modified: unknown,
+                path: p,
+            };
         };
-        let mode = FileMode(p.meta.st_mode()).to_string();
+        let mode = FileMode(meta.mode).to_string();
         let filetype_mode = format!("{filetype}{mode}");
         // number of links
-        let num_links = p.meta.st_nlink();
+        let num_links = meta.nlink;
         // file owner
-        let owner_uid = Uid::from_raw(p.meta.st_uid());
+        let owner_uid = Uid::from_raw(meta.uid);
         let owner_user = User::from_uid(owner_uid).unwrap_or_default();
         let file_owner = owner_user.map(|u| u.name).unwrap_or_default();
         // file groups
-        let owner_gid = Gid::from_raw(p.meta.st_gid());
+        let owner_gid = Gid::from_raw(meta.gid);
         let owner_group = Group::from_gid(owner_gid).unwrap_or_default();
         let file_group = owner_group.map(|g| g.name).unwrap_or_default();
-        // size
-        let size = p.meta.st_size();
+        // size, replaced by the disk usage with `--du`
+        let size = p.usage().unwrap_or(meta.size);
         // last modified
         // when the modified date is more than 1 year ago, the time is replaced by the
         // modification year
-        let last_mod_secs = p.meta.st_mtime();
+        let last_mod_secs = meta.mtime;
         let one_year_ago = SystemTime::now() - Duration::from_secs(60 * 24 * 365);
         let one_year_ago_ts = time_format::from_system_time(one_year_ago).unwrap_or_default();
         let last_modified = if last_mod_secs < one_year_ago_ts {
@@ -111,6 +407,9 @@ impl From<PathInfo> for LongPathInfo {
             num_links: num_links.to_string(),
             file_owner,
             file_group,
+            security_context,
+            capabilities,
+            inode_flags,
             size: size.to_string(),
             last_modified,
             path: p,
@@ -0,0 +1,50 @@
+use std::sync::Mutex;
+use std::thread;
+
+/// Call `f` on every element of `items` using up to `jobs` scoped threads, which take
+/// `chunk_size` elements at a time so that slow elements (e.g. stats on a network filesystem)
+/// do not hold up the rest. `items` keeps its order, and everything runs on the calling thread
+/// when there is only enough work for one chunk.
+pub(crate) fn for_each_parallel<T: Send>(
+    jobs: usize,
+    chunk_size: usize,
+    items: &mut [T],
+    f: impl Fn(&mut T) + Sync,
+) {
+    let workers = std::cmp::min(jobs, items.len().div_ceil(chunk_size));
+    if workers <= 1 {
+        items.iter_mut().for_each(f);
+        return;
+    }
+    let chunks = Mutex::new(items.chunks_mut(chunk_size));
+    thread::scope(|s| {
+        for _ in 0..workers {
+            s.spawn(|| {
+                loop {
+                    let Some(chunk) = chunks.lock().unwrap().next() else {
+                        break;
+                    };
+                    chunk.iter_mut().for_each(&f);
+                }
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_for_each_parallel() {
+        let mut items = (0..1000).collect::<Vec<_>>();
+        let calls = AtomicUsize::new(0);
+        for_each_parallel(8, 7, &mut items, |i| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            *i *= 2;
+        });
+        assert_eq!(calls.into_inner(), 1000);
+        assert_eq!(items, (0..1000).map(|i| i * 2).collect::<Vec<_>>());
+    }
+}
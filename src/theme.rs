@@ -0,0 +1,662 @@
+//! Styles used to colour entries and the fields of the long format, loaded from theme files in
+//! `$XDG_CONFIG_HOME/rusl/themes` for `--theme`.
+//!
+//! A theme file overrides the default styles it names, and its `[light]` and
+//! `[dark]` tables override its other styles depending on the background of the terminal.
+//! Colours are a name such as `blue` or `light-red`, an index into the 256 colour palette, or
+//! `#rrggbb`:
+//! ```toml
+//! size = { fg = "#b58900" }
+//! date = { fg = 244, italic = true }
+//! user = { fg = "yellow", bold = true }
+//! group = { fg = "yellow" }
+//!
+//! [types]
+//! di = { fg = "#268bd2", bold = true }
+//! or = { fg = "white", bg = "red", underline = true }
+//!
+//! [perms]
+//! read = { fg = "yellow" }
+//! write = { fg = "red" }
+//! exec = { fg = "green" }
+//!
+//! [dark.types]
+//! di = { fg = "#83a598", bold = true }
+//! ```
+
+use crate::background::Background;
+use crate::config::{ConfigError, config_dir};
+use crate::filemode::FileKind;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Result as IOResult;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use termion::color;
+use termion::style;
+use toml::{Table, Value};
+
+/// Names of the 16 basic colours, indexed by their number in the palette.
+const COLOR_NAMES: &[&str] = &[
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "light-black",
+    "light-red",
+    "light-green",
+    "light-yellow",
+    "light-blue",
+    "light-magenta",
+    "light-cyan",
+    "light-white",
+];
+
+/// The 16 basic colours as xterm shows them by default, used to pick the closest one to a
+/// colour outside of them. Terminals are free to change these, which is why colours are never
+/// downgraded to them from 256 colours unless they have to be.
+const BASIC_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// Levels of each channel in the 6x6x6 colour cube at 16..=231 of the 256 colour palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// File type codes of `[types]`, as used by `LS_COLORS`, with their default styles.
+const KIND_STYLES: &[(&str, Style)] = &[
+    ("di", Style::new(Some(Color::Fixed(4)), None, true)),
+    ("ln", Style::new(Some(Color::Fixed(6)), None, true)),
+    ("ex", Style::new(Some(Color::Fixed(2)), None, true)),
+    (
+        "ca",
+        Style::new(Some(Color::Fixed(0)), Some(Color::Fixed(1)), false),
+    ),
+    ("or", Style::new(Some(Color::Fixed(1)), None, true)),
+    ("fi", Style::PLAIN),
+    ("pi", Style::PLAIN),
+    ("so", Style::PLAIN),
+    ("cd", Style::PLAIN),
+    ("bd", Style::PLAIN),
+];
+
+/// Styles of `[types]` which differ on dark backgrounds, where blue is hard to read.
+const DARK_KIND_STYLES: &[(&str, Style)] =
+    &[("di", Style::new(Some(Color::Fixed(12)), None, true))];
+
+/// How many colours the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorDepth {
+    /// the 16 basic colours
+    Basic,
+    /// the 256 colour palette
+    Fixed,
+    /// 24-bit RGB colours
+    TrueColor,
+}
+
+impl ColorDepth {
+    /// Detect the colours a terminal supports from the values of `COLORTERM` and `TERM`.
+    pub fn detect(colorterm: Option<&str>, term: Option<&str>) -> Self {
+        let term = term.unwrap_or_default();
+        if matches!(colorterm, Some("truecolor" | "24bit")) || term.ends_with("-direct") {
+            Self::TrueColor
+        } else if term.contains("256color") {
+            Self::Fixed
+        } else {
+            Self::Basic
+        }
+    }
+}
+
+/// A colour of a [`Style`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// an index into the 256 colour palette, whose first 16 are the basic colours
+    Fixed(u8),
+    /// a 24-bit colour
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// Parse a colour name, palette index or `#rrggbb` from a theme file.
+    fn parse(value: &Value) -> Result<Self, String> {
+        let invalid = || format!("invalid colour `{value}`");
+        match value {
+            Value::Integer(n) => u8::try_from(*n).map(Self::Fixed).map_err(|_| invalid()),
+            Value::String(s) => {
+                if let Some(hex) = s.strip_prefix('#') {
+                    let channel = |i: usize| {
+                        hex.get(i..i + 2)
+                            .and_then(|c| u8::from_str_radix(c, 16).ok())
+                    };
+                    match (hex.len(), channel(0), channel(2), channel(4)) {
+                        (6, Some(r), Some(g), Some(b)) => Ok(Self::Rgb(r, g, b)),
+                        _ => Err(invalid()),
+                    }
+                } else {
+                    COLOR_NAMES
+                        .iter()
+                        .position(|name| name == s)
+                        .map(|n| Self::Fixed(n as u8))
+                        .ok_or_else(invalid)
+                }
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    /// The RGB value of the colour, assuming the default xterm palette.
+    fn rgb(self) -> (u8, u8, u8) {
+        match self {
+            Self::Rgb(r, g, b) => (r, g, b),
+            Self::Fixed(n @ 0..16) => BASIC_RGB[n as usize],
+            Self::Fixed(n @ 16..232) => {
+                let n = (n - 16) as usize;
+                (
+                    CUBE_LEVELS[n / 36],
+                    CUBE_LEVELS[n / 6 % 6],
+                    CUBE_LEVELS[n % 6],
+                )
+            }
+            Self::Fixed(n) => {
+                let level = 8 + 10 * (n - 232);
+                (level, level, level)
+            }
+        }
+    }
+
+    /// The closest colour a terminal showing `depth` colours can show.
+    pub fn downgrade(self, depth: ColorDepth) -> Self {
+        match (self, depth) {
+            (_, ColorDepth::TrueColor)
+            | (Self::Fixed(0..16), _)
+            | (Self::Fixed(_), ColorDepth::Fixed) => self,
+            (Self::Rgb(r, g, b), ColorDepth::Fixed) => {
+                // the closest of the colour cube and the greyscale ramp
+                let level = |c: u8| {
+                    (0..CUBE_LEVELS.len())
+                        .min_by_key(|&i| CUBE_LEVELS[i].abs_diff(c))
+                        .unwrap() as u8
+                };
+                let cube = Self::Fixed(16 + 36 * level(r) + 6 * level(g) + level(b));
+                let average = (u16::from(r) + u16::from(g) + u16::from(b)) / 3;
+                let grey = Self::Fixed(232 + (average.saturating_sub(3) / 10).min(23) as u8);
+                [cube, grey]
+                    .into_iter()
+                    .min_by_key(|c| distance(c.rgb(), (r, g, b)))
+                    .unwrap()
+            }
+            (_, ColorDepth::Basic) => {
+                let rgb = self.rgb();
+                (0..16)
+                    .map(Self::Fixed)
+                    .min_by_key(|c| distance(c.rgb(), rgb))
+                    .unwrap()
+            }
+        }
+    }
+
+    fn write_fg(self, w: &mut impl Write) -> IOResult<()> {
+        match self {
+            Self::Fixed(n) => write!(w, "{}", color::Fg(color::AnsiValue(n))),
+            Self::Rgb(r, g, b) => write!(w, "{}", color::Fg(color::Rgb(r, g, b))),
+        }
+    }
+
+    fn write_bg(self, w: &mut impl Write) -> IOResult<()> {
+        match self {
+            Self::Fixed(n) => write!(w, "{}", color::Bg(color::AnsiValue(n))),
+            Self::Rgb(r, g, b) => write!(w, "{}", color::Bg(color::Rgb(r, g, b))),
+        }
+    }
+}
+
+/// The squared distance between two RGB colours.
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| u32::from(x.abs_diff(y)).pow(2);
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+/// How to show a piece of text: its colours and attributes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl Style {
+    /// Text shown as it is.
+    pub const PLAIN: Self = Self::new(None, None, false);
+
+    const fn new(fg: Option<Color>, bg: Option<Color>, bold: bool) -> Self {
+        Self {
+            fg,
+            bg,
+            bold,
+            italic: false,
+            underline: false,
+        }
+    }
+
+    /// Parse a style from a table with the optional keys `fg`, `bg`, `bold`, `italic` and
+    /// `underline`. `key` names the style in errors.
+    fn parse(value: &Value, key: &str) -> Result<Self, String> {
+        let table = value
+            .as_table()
+            .ok_or_else(|| format!("`{key}` must be a table"))?;
+        let mut style = Self::PLAIN;
+        for (name, value) in table {
+            let flag = || {
+                value
+                    .as_bool()
+                    .ok_or_else(|| format!("`{key}.{name}` must be a boolean"))
+            };
+            match name.as_str() {
+                "fg" => style.fg = Some(Color::parse(value)?),
+                "bg" => style.bg = Some(Color::parse(value)?),
+                "bold" => style.bold = flag()?,
+                "italic" => style.italic = flag()?,
+                "underline" => style.underline = flag()?,
+                name => return Err(format!("unknown key `{name}` in `{key}`")),
+            }
+        }
+        Ok(style)
+    }
+
+    /// Whether text is shown as it is.
+    pub fn is_plain(&self) -> bool {
+        *self == Self::PLAIN
+    }
+
+    /// The closest style a terminal showing `depth` colours can show.
+    pub fn downgrade(self, depth: ColorDepth) -> Self {
+        Self {
+            fg: self.fg.map(|c| c.downgrade(depth)),
+            bg: self.bg.map(|c| c.downgrade(depth)),
+            ..self
+        }
+    }
+
+    /// Write `s` in this style, resetting every attribute after it.
+    pub fn write(&self, w: &mut impl Write, s: &str) -> IOResult<()> {
+        if self.is_plain() {
+            return write!(w, "{s}");
+        }
+        if self.bold {
+            write!(w, "{}", style::Bold)?;
+        }
+        if self.italic {
+            write!(w, "{}", style::Italic)?;
+        }
+        if self.underline {
+            write!(w, "{}", style::Underline)?;
+        }
+        if let Some(fg) = self.fg {
+            fg.write_fg(w)?;
+        }
+        if let Some(bg) = self.bg {
+            bg.write_bg(w)?;
+        }
+        write!(w, "{s}{}", style::Reset)
+    }
+}
+
+/// The styles of entry names and of the fields of the long format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    /// styles of entry names by their `LS_COLORS` file type code, e.g. `di` for directories,
+    /// `ex` for executables, `ca` for files with capabilities and `or` for dangling symlink
+    /// targets
+    pub kinds: HashMap<String, Style>,
+    /// style of `r` in permissions
+    pub read: Style,
+    /// style of `w` in permissions
+    pub write: Style,
+    /// style of `x` in permissions
+    pub exec: Style,
+    /// style of the setuid, setgid and sticky bits in permissions
+    pub special: Style,
+    /// style of `-` in permissions
+    pub none: Style,
+    /// style of sizes
+    pub size: Style,
+    /// style of modification times
+    pub date: Style,
+    /// style of owners
+    pub user: Style,
+    /// style of groups
+    pub group: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            kinds: KIND_STYLES
+                .iter()
+                .map(|&(code, style)| (code.to_string(), style))
+                .collect(),
+            read: Style::PLAIN,
+            write: Style::PLAIN,
+            exec: Style::PLAIN,
+            special: Style::PLAIN,
+            none: Style::PLAIN,
+            size: Style::PLAIN,
+            date: Style::PLAIN,
+            user: Style::PLAIN,
+            group: Style::PLAIN,
+        }
+    }
+}
+
+impl Theme {
+    /// The theme file for `--theme name`: `name` itself if it is a path, otherwise `name.toml`
+    /// in the `themes` directory of the config directory.
+    pub fn path(name: &str) -> Option<PathBuf> {
+        if name.contains('/') {
+            Some(PathBuf::from(name))
+        } else {
+            config_dir().map(|dir| dir.join("themes").join(format!("{name}.toml")))
+        }
+    }
+
+    /// The default theme, with the variant for `background` if it is known.
+    pub fn builtin(background: Option<Background>) -> Self {
+        let mut theme = Self::default();
+        if background == Some(Background::Dark) {
+            for &(code, style) in DARK_KIND_STYLES {
+                theme.kinds.insert(code.to_string(), style);
+            }
+        }
+        theme
+    }
+
+    /// Load the theme file at `path`, with its variant for `background` if it is known.
+    pub fn load(path: &Path, background: Option<Background>) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|err| ConfigError::Read {
+            path: path.to_path_buf(),
+            err,
+        })?;
+        Self::parse(&text, background).map_err(|msg| ConfigError::Parse {
+            path: path.to_path_buf(),
+            msg,
+        })
+    }
+
+    /// Parse the contents of a theme file, overriding the styles of the default theme. The
+    /// `[light]` and `[dark]` tables hold styles which override the others on terminals with a
+    /// light or dark `background`.
+    pub fn parse(text: &str, background: Option<Background>) -> Result<Self, String> {
+        let table = text.parse::<Table>().map_err(|err| err.to_string())?;
+        let (variants, styles): (Table, Table) = table
+            .into_iter()
+            .partition(|(key, _)| key == "light" || key == "dark");
+        let mut theme = Self::builtin(background);
+        theme.apply(&styles, "")?;
+        for (key, value) in &variants {
+            // variants which are not used are still checked for mistakes
+            let mut unused = Self::default();
+            let variant = if background.is_some_and(|b| b.name() == key) {
+                &mut theme
+            } else {
+                &mut unused
+            };
+            variant.apply(sections(value, key)?, &format!("{key}."))?;
+        }
+        Ok(theme)
+    }
+
+    /// Override styles with those in `table`, whose keys are named with `prefix` in errors.
+    fn apply(&mut self, table: &Table, prefix: &str) -> Result<(), String> {
+        for (key, value) in table {
+            let key = format!("{prefix}{key}");
+            match &key[prefix.len()..] {
+                "types" => {
+                    for (code, value) in sections(value, &key)? {
+                        if !self.kinds.contains_key(code) {
+                            return Err(format!("unknown file type `{code}` in `{key}`"));
+                        }
+                        let style = Style::parse(value, &format!("{key}.{code}"))?;
+                        self.kinds.insert(code.clone(), style);
+                    }
+                }
+                "perms" => {
+                    for (name, value) in sections(value, &key)? {
+                        let style = Style::parse(value, &format!("{key}.{name}"))?;
+                        match name.as_str() {
+                            "read" => self.read = style,
+                            "write" => self.write = style,
+                            "exec" => self.exec = style,
+                            "special" => self.special = style,
+                            "none" => self.none = style,
+                            name => return Err(format!("unknown key `{name}` in `{key}`")),
+                        }
+                    }
+                }
+                "size" => self.size = Style::parse(value, &key)?,
+                "date" => self.date = Style::parse(value, &key)?,
+                "user" => self.user = Style::parse(value, &key)?,
+                "group" => self.group = Style::parse(value, &key)?,
+                _ => return Err(format!("unknown key `{key}`")),
+            }
+        }
+        Ok(())
+    }
+
+    /// The closest theme a terminal showing `depth` colours can show.
+    pub fn downgrade(self, depth: ColorDepth) -> Self {
+        let downgrade = |style: Style| style.downgrade(depth);
+        Self {
+            kinds: self
+                .kinds
+                .into_iter()
+                .map(|(code, style)| (code, downgrade(style)))
+                .collect(),
+            read: downgrade(self.read),
+            write: downgrade(self.write),
+            exec: downgrade(self.exec),
+            special: downgrade(self.special),
+            none: downgrade(self.none),
+            size: downgrade(self.size),
+            date: downgrade(self.date),
+            user: downgrade(self.user),
+            group: downgrade(self.group),
+        }
+    }
+
+    /// The style of the `LS_COLORS` file type `code`.
+    fn kind(&self, code: &str) -> Style {
+        self.kinds.get(code).copied().unwrap_or_default()
+    }
+
+    /// The style of the name of an entry of type `kind`, which is `executable` or `capable`,
+    /// i.e. has file capabilities, which takes precedence like `ca` does over `ex` in
+    /// `LS_COLORS`.
+    pub fn name(&self, kind: FileKind, executable: bool, capable: bool) -> Style {
+        match kind {
+            FileKind::Dir => self.kind("di"),
+            FileKind::Symlink => self.kind("ln"),
+            _ if capable => self.kind("ca"),
+            _ if executable => self.kind("ex"),
+            FileKind::File => self.kind("fi"),
+            FileKind::Fifo => self.kind("pi"),
+            FileKind::Socket => self.kind("so"),
+            FileKind::CharDevice => self.kind("cd"),
+            FileKind::BlockDevice => self.kind("bd"),
+            FileKind::Unknown => Style::PLAIN,
+        }
+    }
+
+    /// The style of the target of a symlink which does not lead to anything.
+    pub fn orphan(&self) -> Style {
+        self.kind("or")
+    }
+
+    /// The style of the character `c` of a permission string.
+    pub fn perm(&self, c: char) -> Style {
+        match c {
+            'r' => self.read,
+            'w' => self.write,
+            'x' => self.exec,
+            's' | 'S' | 't' | 'T' => self.special,
+            '-' => self.none,
+            _ => Style::PLAIN,
+        }
+    }
+}
+
+/// The named tables inside the table `key`.
+fn sections<'a>(value: &'a Value, key: &str) -> Result<&'a Table, String> {
+    value
+        .as_table()
+        .ok_or_else(|| format!("`{key}` must be a table"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_theme() {
+        let theme = Theme::parse(
+            r##"
+size = { fg = "#b58900", italic = true }
+user = { fg = 214, bg = "light-black", underline = true }
+
+[types]
+di = { fg = "#268bd2" }
+
+[perms]
+exec = { fg = "green", bold = true }
+"##,
+            None,
+        )
+        .unwrap();
+        assert_eq!(theme.size.fg, Some(Color::Rgb(0xb5, 0x89, 0x00)));
+        assert!(theme.size.italic && !theme.size.bold);
+        assert_eq!(theme.user.bg, Some(Color::Fixed(8)));
+        assert_eq!(
+            theme.name(FileKind::Dir, false, false).fg,
+            Some(Color::Rgb(0x26, 0x8b, 0xd2))
+        );
+        // styles the file does not mention keep their defaults
+        assert_eq!(theme.orphan(), Theme::default().orphan());
+        assert_eq!(theme.perm('x').fg, Some(Color::Fixed(2)));
+        assert!(theme.perm('.').is_plain());
+
+        let mut out = Vec::new();
+        theme.user.write(&mut out, "root").unwrap();
+        assert_eq!(out, b"\x1b[4m\x1b[38;5;214m\x1b[48;5;8mroot\x1b[m");
+
+        let parse = |text: &str| Theme::parse(text, None).unwrap_err();
+        assert_eq!(
+            parse("[types]\nzz = {}"),
+            "unknown file type `zz` in `types`"
+        );
+        assert_eq!(
+            parse("date = { fg = \"#12345\" }"),
+            "invalid colour `\"#12345\"`"
+        );
+        assert_eq!(parse("date = { fg = 256 }"), "invalid colour `256`");
+        assert_eq!(
+            parse("date = { bold = 1 }"),
+            "`date.bold` must be a boolean"
+        );
+        assert_eq!(
+            parse("[dark.perms]\nx = {}"),
+            "unknown key `x` in `dark.perms`"
+        );
+    }
+
+    #[test]
+    fn test_theme_variants() {
+        let text = r#"
+date = { fg = "blue" }
+
+[light]
+date = { fg = "black" }
+
+[dark]
+date = { fg = "white" }
+size = { bold = true }
+"#;
+        let theme = |background| Theme::parse(text, background).unwrap();
+        assert_eq!(theme(None).date.fg, Some(Color::Fixed(4)));
+        assert!(theme(None).size.is_plain());
+        let light = theme(Some(Background::Light));
+        assert_eq!(light.date.fg, Some(Color::Fixed(0)));
+        assert!(light.size.is_plain());
+        let dark = theme(Some(Background::Dark));
+        assert_eq!(dark.date.fg, Some(Color::Fixed(7)));
+        assert!(dark.size.bold);
+        // the default theme has a variant for dark backgrounds too
+        assert_eq!(
+            dark.name(FileKind::Dir, false, false).fg,
+            Some(Color::Fixed(12))
+        );
+        assert_eq!(
+            Theme::builtin(Some(Background::Light)),
+            Theme::builtin(None)
+        );
+    }
+
+    #[test]
+    fn test_downgrade_colors() {
+        let orange = Color::Rgb(0xff, 0x87, 0x00);
+        assert_eq!(orange.downgrade(ColorDepth::TrueColor), orange);
+        assert_eq!(orange.downgrade(ColorDepth::Fixed), Color::Fixed(208));
+        assert_eq!(orange.downgrade(ColorDepth::Basic), Color::Fixed(3));
+        // greys land on the greyscale ramp rather than the colour cube
+        assert_eq!(
+            Color::Rgb(0x80, 0x80, 0x80).downgrade(ColorDepth::Fixed),
+            Color::Fixed(244)
+        );
+        assert_eq!(
+            Color::Fixed(21).downgrade(ColorDepth::Basic),
+            Color::Fixed(4)
+        );
+        assert_eq!(
+            Color::Fixed(9).downgrade(ColorDepth::Basic),
+            Color::Fixed(9)
+        );
+        // the default theme only uses the basic colours
+        assert_eq!(
+            Theme::default().downgrade(ColorDepth::Basic),
+            Theme::default()
+        );
+    }
+
+    #[test]
+    fn test_detect_depth() {
+        let detect = ColorDepth::detect;
+        assert_eq!(
+            detect(Some("truecolor"), Some("xterm")),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(detect(None, Some("xterm-direct")), ColorDepth::TrueColor);
+        assert_eq!(detect(None, Some("tmux-256color")), ColorDepth::Fixed);
+        assert_eq!(detect(Some("yes"), Some("linux")), ColorDepth::Basic);
+        assert_eq!(detect(None, None), ColorDepth::Basic);
+    }
+}
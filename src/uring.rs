@@ -0,0 +1,174 @@
+//! Batched `statx` calls through io_uring, enabled by the `io-uring` feature.
+
+use crate::constants::*;
+use crate::display::{DisplayOptions, needs_meta};
+use crate::pathinfo::{Meta, PathInfo, count_stat};
+use io_uring::{IoUring, Probe, opcode, types};
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::io;
+use std::io::{Error, ErrorKind};
+use std::mem;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+thread_local! {
+    /// A ring per thread, or `None` if io_uring turned out to be unusable.
+    static RING: RefCell<Option<Option<StatxRing>>> = const { RefCell::new(None) };
+}
+
+/// An io_uring instance which is known to support `statx`.
+pub struct StatxRing {
+    ring: IoUring,
+}
+
+impl StatxRing {
+    /// Set up a ring, failing with `ErrorKind::Unsupported` if the kernel cannot issue `statx`
+    /// through io_uring (before Linux 5.6).
+    pub fn new() -> io::Result<Self> {
+        let ring = IoUring::new(URING_BATCH_SIZE as u32)?;
+        let mut probe = Probe::new();
+        ring.submitter().register_probe(&mut probe)?;
+        if !probe.is_supported(opcode::Statx::CODE) {
+            return Err(Error::from(ErrorKind::Unsupported));
+        }
+        Ok(Self { ring })
+    }
+
+    /// Stat every path in `paths` without following symlinks, resolving relative paths against
+    /// the directory `dir` and submitting up to `URING_BATCH_SIZE` requests at once. Returns
+    /// the metadata of each path in order, or `None` for paths which could not be stat-ed.
+    pub fn lstat_all(&mut self, dir: impl AsFd, paths: &[&Path]) -> io::Result<Vec<Option<Meta>>> {
+        let mut metas = Vec::with_capacity(paths.len());
+        for batch in paths.chunks(URING_BATCH_SIZE) {
+            metas.extend(self.lstat_batch(dir.as_fd(), batch)?);
+        }
+        Ok(metas)
+    }
+
+    fn lstat_batch(
+        &mut self,
+        dir: BorrowedFd<'_>,
+        paths: &[&Path],
+    ) -> io::Result<Vec<Option<Meta>>> {
+        let names = paths
+            .iter()
+            .map(|p| CString::new(p.as_os_str().as_bytes()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+        // SAFETY: `statx` is plain old data, for which all zeroes is a valid value
+        let mut bufs = vec![unsafe { mem::zeroed::<libc::statx>() }; paths.len()];
+        {
+            let mut queue = self.ring.submission();
+            for (ind, (name, buf)) in names.iter().zip(bufs.iter_mut()).enumerate() {
+                let buf: *mut libc::statx = buf;
+                let entry =
+                    opcode::Statx::new(types::Fd(dir.as_raw_fd()), name.as_ptr(), buf.cast())
+                        .flags(libc::AT_SYMLINK_NOFOLLOW)
+                        .mask(libc::STATX_BASIC_STATS)
+                        .build()
+                        .user_data(ind as u64);
+                // SAFETY: `name` and `buf` stay alive and in place until every request in the
+                // batch has completed, or are leaked below
+                unsafe { queue.push(&entry) }.expect("a batch fits in the submission queue");
+            }
+        }
+
+        let mut metas = vec![None; paths.len()];
+        let mut completed = 0;
+        while completed < paths.len() {
+            match self.ring.submit_and_wait(paths.len() - completed) {
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    // requests may still be in flight, so what they point to must stay valid
+                    mem::forget(names);
+                    mem::forget(bufs);
+                    return Err(err);
+                }
+            }
+            for cqe in self.ring.completion() {
+                let ind = cqe.user_data() as usize;
+                count_stat();
+                if cqe.result() >= 0 {
+                    metas[ind] = Some(meta_from_statx(&bufs[ind]));
+                }
+                completed += 1;
+            }
+        }
+        Ok(metas)
+    }
+}
+
+fn meta_from_statx(stx: &libc::statx) -> Meta {
+    Meta {
+        mode: stx.stx_mode.into(),
+        nlink: stx.stx_nlink.into(),
+        uid: stx.stx_uid,
+        gid: stx.stx_gid,
+        size: stx.stx_size,
+        blocks: stx.stx_blocks,
+        mtime: stx.stx_mtime.tv_sec,
+        dev: libc::makedev(stx.stx_dev_major, stx.stx_dev_minor),
+        ino: stx.stx_ino,
+    }
+}
+
+/// Fetch the metadata needed to display `paths` relative to `dir`, the directory containing
+/// them, in batches through this thread's ring.
+/// Returns `false` without fetching anything if io_uring is unavailable, so the caller can fall
+/// back to stat-ing synchronously.
+pub(crate) fn prefetch_meta(
+    opts: &DisplayOptions,
+    dir: BorrowedFd<'_>,
+    paths: &mut [PathInfo],
+) -> bool {
+    RING.with_borrow_mut(|ring| {
+        let Some(ring) = ring.get_or_insert_with(|| StatxRing::new().ok()) else {
+            return false;
+        };
+        let mut targets = paths
+            .iter_mut()
+            .filter(|p| needs_meta(opts, p))
+            .collect::<Vec<_>>();
+        let names = targets
+            .iter()
+            .map(|p| Path::new(p.path.file_name().unwrap_or_default()))
+            .collect::<Vec<_>>();
+        let Ok(metas) = ring.lstat_all(dir, &names) else {
+            return false;
+        };
+        for (p, meta) in targets.iter_mut().zip(metas) {
+            p.set_meta(meta);
+        }
+        true
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pathinfo::lstat;
+    use std::fs::File;
+
+    #[test]
+    fn test_lstat_all_matches_lstat() {
+        let Ok(mut ring) = StatxRing::new() else {
+            // io_uring is unavailable here, which callers fall back from
+            return;
+        };
+        let src = File::open(Path::new(env!("CARGO_MANIFEST_DIR")).join("src")).unwrap();
+        let paths = [
+            Path::new(env!("CARGO_MANIFEST_DIR")),
+            Path::new("uring.rs"),
+            Path::new("/proc/self"),
+            Path::new("/does/not/exist"),
+        ];
+        let metas = ring.lstat_all(&src, &paths).unwrap();
+        for (path, meta) in paths.iter().zip(metas) {
+            let expected = lstat(&Path::new(env!("CARGO_MANIFEST_DIR")).join("src").join(path));
+            assert_eq!(meta, expected.ok(), "{}", path.display());
+        }
+    }
+}
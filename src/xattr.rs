@@ -0,0 +1,304 @@
+//! Extended attributes of listed entries for `-@`/`--xattrs`.
+
+use nix::fcntl::{OFlag, openat};
+use nix::sys::stat::Mode;
+use std::ffi::{CString, OsStr, OsString};
+use std::io;
+use std::io::Result as IOResult;
+use std::os::fd::{AsRawFd, BorrowedFd, OwnedFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::Path;
+
+/// Extended attribute holding the SELinux security context of a file.
+const SELINUX_XATTR: &str = "security.selinux";
+
+/// An extended attribute of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Xattr {
+    pub name: OsString,
+    pub value: Vec<u8>,
+}
+
+impl Xattr {
+    /// The value as text if it is printable UTF-8, ignoring a trailing nul, otherwise as hex
+    /// prefixed by `0x`.
+    pub fn display_value(&self) -> String {
+        let value = self.value.strip_suffix(b"\0").unwrap_or(&self.value);
+        match std::str::from_utf8(value) {
+            Ok(text) if !text.chars().any(char::is_control) => text.to_string(),
+            _ => {
+                let hex = self.value.iter().map(|b| format!("{b:02x}"));
+                format!("0x{}", hex.collect::<String>())
+            }
+        }
+    }
+}
+
+fn c_path(path: &Path) -> IOResult<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(io::Error::from)
+}
+
+/// Call `f` with a buffer of growing size until the result fits, the way the xattr syscalls
+/// need to be used when the size can change between calls. `f` returns the number of bytes
+/// written, or the size needed if the buffer is empty.
+fn read_sized(mut f: impl FnMut(&mut [u8]) -> isize) -> IOResult<Vec<u8>> {
+    loop {
+        let size = f(&mut []);
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buf = vec![0; size as usize];
+        if buf.is_empty() {
+            return Ok(buf);
+        }
+        match f(&mut buf) {
+            len if len >= 0 => {
+                buf.truncate(len as usize);
+                return Ok(buf);
+            }
+            // the value grew since its size was read
+            _ if io::Error::last_os_error().raw_os_error() == Some(libc::ERANGE) => continue,
+            _ => return Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+/// A file whose extended attributes are read without following symlinks.
+///
+/// Entries of an open directory are opened by name with `O_PATH`, which works for any type
+/// of file without side effects, and read through their `/proc/self/fd` link. Following the
+/// link leads to the entry itself, so deep trees are not limited by `PATH_MAX` and the entry
+/// cannot be swapped by renaming its ancestors. The xattr syscalls do not accept `O_PATH` file
+/// descriptors themselves.
+pub(crate) struct XattrFile {
+    path: CString,
+    /// whether `path` is the `/proc/self/fd` link of `_fd`, which must be followed
+    follow: bool,
+    _fd: Option<OwnedFd>,
+}
+
+impl XattrFile {
+    /// Open `path` relative to the open directory `dir` if it is given, or by path otherwise.
+    pub(crate) fn open(dir: Option<BorrowedFd<'_>>, path: &Path) -> IOResult<Self> {
+        match (dir, path.file_name()) {
+            (Some(dir), Some(name)) => {
+                let flags = OFlag::O_PATH | OFlag::O_NOFOLLOW | OFlag::O_CLOEXEC;
+                let fd = openat(dir, name, flags, Mode::empty())?;
+                Ok(Self {
+                    path: CString::new(format!("/proc/self/fd/{}", fd.as_raw_fd()))?,
+                    follow: true,
+                    _fd: Some(fd),
+                })
+            }
+            _ => Ok(Self {
+                path: c_path(path)?,
+                follow: false,
+                _fd: None,
+            }),
+        }
+    }
+
+    /// The names of the extended attributes of the file. Filesystems without extended
+    /// attributes have none.
+    pub(crate) fn names(&self) -> IOResult<Vec<OsString>> {
+        let path = self.path.as_ptr();
+        // SAFETY: `path` is nul terminated and `buf` is valid for writes of `buf.len()` bytes
+        let list = read_sized(|buf| unsafe {
+            if self.follow {
+                libc::listxattr(path, buf.as_mut_ptr().cast(), buf.len())
+            } else {
+                libc::llistxattr(path, buf.as_mut_ptr().cast(), buf.len())
+            }
+        });
+        match list {
+            Ok(list) => Ok(list
+                .split(|&b| b == 0)
+                .filter(|name| !name.is_empty())
+                .map(|name| OsString::from_vec(name.to_vec()))
+                .collect()),
+            Err(err) if err.raw_os_error() == Some(libc::ENOTSUP) => Ok(Vec::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The value of the extended attribute `name` of the file, or `None` if it does not have
+    /// one.
+    pub(crate) fn get(&self, name: &OsStr) -> IOResult<Option<Vec<u8>>> {
+        let path = self.path.as_ptr();
+        let name = CString::new(name.as_bytes()).map_err(io::Error::from)?;
+        // SAFETY: `path` and `name` are nul terminated and `buf` is valid for writes of
+        // `buf.len()` bytes
+        let value = read_sized(|buf| unsafe {
+            let (name, value) = (name.as_ptr(), buf.as_mut_ptr().cast());
+            if self.follow {
+                libc::getxattr(path, name, value, buf.len())
+            } else {
+                libc::lgetxattr(path, name, value, buf.len())
+            }
+        });
+        match value {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if matches!(err.raw_os_error(), Some(libc::ENODATA | libc::ENOTSUP)) => {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// The value of the extended attribute `name` of `path` without following symlinks, relative
+/// to the open directory `dir` if it is given, or `None` if it does not have one.
+pub fn get(dir: Option<BorrowedFd<'_>>, path: &Path, name: &OsStr) -> IOResult<Option<Vec<u8>>> {
+    XattrFile::open(dir, path)?.get(name)
+}
+
+/// The SELinux security context of `path` without following symlinks, relative to the open
+/// directory `dir` if it is given, or `None` if it has none or it cannot be read.
+pub fn security_context(dir: Option<BorrowedFd<'_>>, path: &Path) -> Option<String> {
+    let value = get(dir, path, OsStr::new(SELINUX_XATTR)).ok()??;
+    let value = value.strip_suffix(b"\0").unwrap_or(&value);
+    Some(String::from_utf8_lossy(value).into_owned())
+}
+
+/// Every extended attribute of `path` with its value, in the order they are listed, relative to
+/// the open directory `dir` if it is given. Attributes removed while they are read are left out.
+pub fn list(dir: Option<BorrowedFd<'_>>, path: &Path) -> IOResult<Vec<Xattr>> {
+    let file = XattrFile::open(dir, path)?;
+    let mut xattrs = Vec::new();
+    for name in file.names()? {
+        if let Some(value) = file.get(&name)? {
+            xattrs.push(Xattr { name, value });
+        }
+    }
+    Ok(xattrs)
+}
+
+/// Set the extended attribute `name` of `path` to `value`, for tests.
+#[cfg(test)]
+pub(crate) fn set(path: &Path, name: &str, value: &[u8]) {
+    try_set(path, name, value).unwrap();
+}
+
+/// Like `set()`, but returns the error for attributes which need privileges, e.g.
+/// `CAP_SETFCAP` for `security.capability`, so tests can be skipped without them.
+#[cfg(test)]
+pub(crate) fn try_set(path: &Path, name: &str, value: &[u8]) -> IOResult<()> {
+    let path = c_path(path)?;
+    let name = CString::new(name).map_err(io::Error::from)?;
+    // SAFETY: the strings are nul terminated and `value` is valid for reads of its length
+    let res = unsafe {
+        libc::lsetxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+        )
+    };
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::display::test::{fixture, render};
+    use crate::lister::Lister;
+    use std::fs;
+    use std::fs::File;
+    use std::os::fd::AsFd;
+
+    #[test]
+    fn test_list_xattrs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("file");
+        fs::write(&path, "").unwrap();
+        assert_eq!(list(None, &path).unwrap(), []);
+
+        set(&path, "user.text", b"hello\0");
+        set(&path, "user.bytes", &[0xff, 0x01]);
+        // entries of an open directory are read through it, by name
+        let dir = File::open(tmp.path()).unwrap();
+        for dir in [None, Some(dir.as_fd())] {
+            let xattrs = list(dir, &path).unwrap();
+            let values = xattrs
+                .iter()
+                .map(|x| (x.name.to_str().unwrap(), x.display_value()))
+                .collect::<Vec<_>>();
+            assert!(values.contains(&("user.text", "hello".to_string())));
+            assert!(values.contains(&("user.bytes", "0xff01".to_string())));
+            assert_eq!(get(dir, &path, OsStr::new("user.missing")).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_xattrs_long() {
+        let tmp = fixture();
+        set(&tmp.path().join("alpha"), "user.origin", b"cache");
+        set(&tmp.path().join("alpha"), "user.id", &[1, 2]);
+        let files = ["alpha", "beta.txt"].map(|f| tmp.path().join(f));
+        let lister = Lister::new()
+            .paths(&files)
+            .long(true)
+            .color(false)
+            .xattrs(true);
+        let out = render(lister.clone());
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4, "{out}");
+        assert!(lines[0].starts_with("-rw-r--r--@ 1 "), "{out}");
+        assert_eq!(lines[1..3], ["    user.id      2", "    user.origin  5"]);
+        // entries without extended attributes are padded to keep the columns aligned
+        assert!(lines[3].starts_with("-rw-r--r--  1 "), "{out}");
+
+        let out = render(lister.xattr_values(true));
+        assert!(
+            out.contains("\n    user.id      0x0102\n    user.origin  cache\n"),
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn test_security_context() {
+        let tmp = fixture();
+        let context = b"system_u:object_r:etc_t:s0\0";
+        set(&tmp.path().join("alpha"), SELINUX_XATTR, context);
+        let dir = File::open(tmp.path()).unwrap();
+        assert_eq!(
+            security_context(Some(dir.as_fd()), Path::new("alpha")).as_deref(),
+            Some("system_u:object_r:etc_t:s0")
+        );
+        assert_eq!(
+            security_context(Some(dir.as_fd()), Path::new("beta.txt")),
+            None
+        );
+
+        let lister = Lister::new().color(false).context(true);
+        // entries of a listed directory are read relative to it, without following symlinks
+        let out = render(lister.clone().path(tmp.path()).one_per_line(true));
+        assert!(
+            out.starts_with("system_u:object_r:etc_t:s0 alpha\n? beta.txt\n? docs/\n? link\n"),
+            "{out}"
+        );
+        // the context and the space after it count towards the column widths
+        let files = ["alpha", "beta.txt"].map(|f| tmp.path().join(f));
+        let lister = lister.paths(&files);
+        let out = render(lister.clone().term_cols(45));
+        assert_eq!(out, "system_u:object_r:etc_t:s0 alpha  ? beta.txt\n");
+        let out = render(lister.clone().term_cols(44));
+        assert_eq!(out, "system_u:object_r:etc_t:s0 alpha\n? beta.txt\n");
+
+        let out = render(lister.long(true));
+        let lines = out.lines().collect::<Vec<_>>();
+        assert!(
+            lines[0].contains(" system_u:object_r:etc_t:s0 5 Jun 15 2020 alpha"),
+            "{out}"
+        );
+        assert!(
+            lines[1].contains(" ?                          8 Jun 15 2020 beta.txt"),
+            "{out}"
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! Fixture trees shared by the integration tests.
+#![allow(dead_code)]
+
+use itertools::Itertools;
+use nix::fcntl::AT_FDCWD;
+use nix::sys::stat::{Mode, UtimensatFlags, utimensat};
+use nix::sys::time::TimeSpec;
+use nix::unistd::mkfifo;
+use std::fs;
+use std::os::unix::fs::{PermissionsExt, symlink};
+use std::path::Path;
+use std::process::{Command, Output};
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// Jun 15 2020 12:00 UTC, old enough that only the date is shown.
+pub const FIXTURE_MTIME: u64 = 1_592_222_400;
+
+/// Set the modification and access time of `path` to `FIXTURE_MTIME` without following symlinks.
+pub fn set_fixture_mtime(path: &Path) {
+    let time = TimeSpec::from_duration(Duration::from_secs(FIXTURE_MTIME));
+    utimensat(
+        AT_FDCWD,
+        path,
+        &time,
+        &time,
+        UtimensatFlags::NoFollowSymlink,
+    )
+    .unwrap();
+}
+
+/// Create a file at `path` containing `contents` with permissions `mode`.
+pub fn write_file(path: &Path, contents: &str, mode: u32) {
+    fs::write(path, contents).unwrap();
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+}
+
+/// Create the fixture tree used by the integration tests:
+/// ```text
+/// -dash
+/// .config/
+///   settings
+/// docs/
+///   guide.md
+///   img/
+///     logo.png
+/// flat/
+///   .hidden
+///   alpha
+///   broken -> missing
+///   link -> alpha
+///   pipe (FIFO)
+///   run.sh (executable)
+///   secret (mode 600)
+///   with space
+///   ünïcode
+/// zeta
+/// ```
+/// `flat` contains no directories so that long format listings of it do not depend on
+/// the directory sizes reported by the underlying filesystem. Every entry has the mtime
+/// `FIXTURE_MTIME`.
+pub fn fixture() -> TempDir {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    for dir in [".config", "docs", "docs/img", "flat"] {
+        fs::create_dir(root.join(dir)).unwrap();
+        fs::set_permissions(root.join(dir), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    for (name, mode) in [
+        ("-dash", 0o644),
+        (".config/settings", 0o644),
+        ("docs/guide.md", 0o644),
+        ("docs/img/logo.png", 0o644),
+        ("flat/.hidden", 0o644),
+        ("flat/alpha", 0o644),
+        ("flat/run.sh", 0o755),
+        ("flat/secret", 0o600),
+        ("flat/with space", 0o644),
+        ("flat/ünïcode", 0o644),
+        ("zeta", 0o644),
+    ] {
+        write_file(&root.join(name), name, mode);
+    }
+    symlink("alpha", root.join("flat/link")).unwrap();
+    symlink("missing", root.join("flat/broken")).unwrap();
+    mkfifo(&root.join("flat/pipe"), Mode::from_bits_truncate(0o644)).unwrap();
+
+    // children first, so setting their times does not disturb their parents'
+    for name in [
+        "-dash",
+        ".config/settings",
+        ".config",
+        "docs/guide.md",
+        "docs/img/logo.png",
+        "docs/img",
+        "docs",
+        "flat/.hidden",
+        "flat/alpha",
+        "flat/broken",
+        "flat/link",
+        "flat/pipe",
+        "flat/run.sh",
+        "flat/secret",
+        "flat/with space",
+        "flat/ünïcode",
+        "flat",
+        "zeta",
+    ] {
+        set_fixture_mtime(&root.join(name));
+    }
+    tmp
+}
+
+/// Run the `rusl` binary in `dir` with `args` and a fixed environment.
+pub fn run_rusl(dir: &Path, columns: usize, args: &[&str]) -> Output {
+    run_rusl_with_env(dir, columns, args, &[])
+}
+
+/// Like `run_rusl`, with the variables in `env` added to the environment.
+pub fn run_rusl_with_env(
+    dir: &Path,
+    columns: usize,
+    args: &[&str],
+    env: &[(&str, &str)],
+) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rusl"))
+        .args(args)
+        .current_dir(dir)
+        .env_clear()
+        .env("COLUMNS", columns.to_string())
+        .env("TZ", "UTC")
+        .env("LC_ALL", "C")
+        .envs(env.iter().copied())
+        .output()
+        .unwrap()
+}
+
+/// Every subset of `flags`, in a stable order.
+pub fn subsets(flags: &[&'static str]) -> Vec<Vec<&'static str>> {
+    (0..=flags.len())
+        .flat_map(|k| flags.iter().copied().combinations(k))
+        .collect()
+}
@@ -0,0 +1,276 @@
+//! Differential tests comparing the output of the binary with GNU coreutils `ls`.
+//!
+//! The long format is compared field by field regardless of alignment, leaving out the parts in
+//! `KNOWN_DIFFERENCES` which intentionally differ. The tests pass trivially when the `ls` on
+//! `PATH` is not GNU `ls`. When the property test fails, proptest shrinks the generated tree and
+//! flags and reports the minimal mismatching case.
+
+mod common;
+
+use itertools::Itertools;
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::sample::{select, subsequence};
+use std::collections::HashSet;
+use std::fs;
+use std::iter;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+use std::process::Command;
+
+/// rusl flags which have a GNU `ls` equivalent.
+const FLAGS: &[&str] = &["-a", "-l", "-x", "--dirs-first"];
+
+/// The fields of a long format row before the name, and how many words each takes.
+const LONG_FIELDS: &[(&str, usize)] = &[
+    ("mode", 1),
+    ("links", 1),
+    ("owner", 1),
+    ("group", 1),
+    ("size", 1),
+    ("date", 3),
+];
+
+/// Parts of the long format which intentionally differ from GNU `ls`, and why.
+const KNOWN_DIFFERENCES: &[(&str, &str)] = &[(
+    "total",
+    "rusl shows the size of the listed directory itself",
+)];
+
+fn is_known_difference(part: &str) -> bool {
+    KNOWN_DIFFERENCES.iter().any(|(known, _)| *known == part)
+}
+
+/// Leave out the parts of the long format `output` in `KNOWN_DIFFERENCES`, and join the
+/// remaining fields of each row with single spaces so their alignment does not matter. Lines
+/// which are not rows, e.g. errors, are kept as they are.
+fn compared_long_fields(output: &str) -> String {
+    let mut compared = String::new();
+    for line in output.lines() {
+        if line.starts_with("total ") && is_known_difference("total") {
+            continue;
+        }
+        let mut rest = line;
+        let mut fields = Vec::new();
+        let words = LONG_FIELDS
+            .iter()
+            .flat_map(|&(field, words)| iter::repeat_n(field, words));
+        let mut is_row = true;
+        for field in words {
+            let Some((word, after)) = rest.trim_start().split_once(' ') else {
+                is_row = false;
+                break;
+            };
+            if !is_known_difference(field) {
+                fields.push(word);
+            }
+            rest = after;
+        }
+        if is_row {
+            // the name is everything after the date, whatever spaces it contains
+            fields.push(rest.trim_start());
+            compared.push_str(&fields.join(" "));
+        } else {
+            compared.push_str(line);
+        }
+        compared.push('\n');
+    }
+    compared
+}
+
+/// Translate rusl `flags` into the GNU `ls` arguments producing the same format. rusl appends
+/// `/` to directories and never uses tabs to align columns.
+fn gnu_args(flags: &[&str]) -> Vec<&'static str> {
+    let mut args = vec!["--color=never", "-p", "--tabsize=0", "-C"];
+    // rusl always prefers `-l` over `-x`, GNU `ls` the format given last
+    args.extend(
+        flags
+            .iter()
+            .sorted_by_key(|&&flag| flag == "-l")
+            .map(|&flag| match flag {
+                // rusl never lists `.` and `..`
+                "-a" => "-A",
+                "-l" => "-l",
+                "-x" => "-x",
+                "--dirs-first" => "--group-directories-first",
+                _ => unreachable!("no GNU equivalent for {flag}"),
+            }),
+    );
+    args
+}
+
+/// Check if the `ls` on `PATH` is GNU `ls`.
+fn has_gnu_ls() -> bool {
+    Command::new("ls")
+        .arg("--version")
+        .output()
+        .is_ok_and(|out| String::from_utf8_lossy(&out.stdout).contains("GNU coreutils"))
+}
+
+/// Remove terminal escape sequences, since GNU `ls` is run without colours.
+fn strip_escapes(s: &str) -> String {
+    let mut stripped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // skip the control sequence up to and including its final byte
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+/// Run both tools in `dir` over `operand` and return the output of rusl and GNU `ls`.
+fn outputs(dir: &Path, columns: usize, flags: &[&'static str], operand: &str) -> (String, String) {
+    let rusl_args = [flags, &[operand]].concat();
+    let rusl = common::run_rusl(dir, columns, &rusl_args);
+    let ls = Command::new("ls")
+        .args(gnu_args(flags))
+        .arg(operand)
+        .current_dir(dir)
+        .env_clear()
+        .env("COLUMNS", columns.to_string())
+        .env("TZ", "UTC")
+        // rusl measures names as UTF-8, which `C.UTF-8` sorts in the same byte order as `C`
+        .env("LC_ALL", "C.UTF-8")
+        .output()
+        .unwrap();
+    let combined = |out: std::process::Output| {
+        format!(
+            "{}{}",
+            String::from_utf8_lossy(&out.stdout),
+            String::from_utf8_lossy(&out.stderr)
+        )
+    };
+    let (rusl, ls) = (strip_escapes(&combined(rusl)), combined(ls));
+    if flags.contains(&"-l") {
+        (compared_long_fields(&rusl), compared_long_fields(&ls))
+    } else {
+        (rusl, ls)
+    }
+}
+
+/// Describe the lines which differ between `ls` and `rusl`.
+fn diff_lines(ls: &str, rusl: &str) -> String {
+    let ls_lines = ls.lines().collect::<Vec<_>>();
+    let rusl_lines = rusl.lines().collect::<Vec<_>>();
+    let mut diff = String::new();
+    for ind in 0..std::cmp::max(ls_lines.len(), rusl_lines.len()) {
+        let (expected, actual) = (ls_lines.get(ind), rusl_lines.get(ind));
+        if expected != actual {
+            if let Some(line) = expected {
+                diff.push_str(&format!("-{line:?}\n"));
+            }
+            if let Some(line) = actual {
+                diff.push_str(&format!("+{line:?}\n"));
+            }
+        }
+    }
+    diff
+}
+
+#[test]
+fn test_fixture_matches_gnu_ls() {
+    if !has_gnu_ls() {
+        return;
+    }
+    let tmp = common::fixture();
+    let mut mismatches = Vec::new();
+    for flags in common::subsets(FLAGS) {
+        for operand in [".", "flat", "docs"] {
+            for columns in [80, 30] {
+                let (rusl, ls) = outputs(tmp.path(), columns, &flags, operand);
+                if rusl != ls {
+                    mismatches.push(format!(
+                        "COLUMNS={columns} rusl {} {operand}:\n{}",
+                        flags.join(" "),
+                        diff_lines(&ls, &rusl)
+                    ));
+                }
+            }
+        }
+    }
+    assert!(
+        mismatches.is_empty(),
+        "{} run(s) differ from GNU ls (- ls, + rusl):\n\n{}",
+        mismatches.len(),
+        mismatches.join("\n")
+    );
+}
+
+/// A randomly generated directory tree entry.
+#[derive(Debug, Clone)]
+enum Node {
+    File { name: String, mode: u32 },
+    Symlink { name: String, target: String },
+    Dir { name: String, children: Vec<Node> },
+}
+
+impl Node {
+    fn name(&self) -> &str {
+        match self {
+            Self::File { name, .. } | Self::Symlink { name, .. } | Self::Dir { name, .. } => name,
+        }
+    }
+}
+
+fn name() -> impl Strategy<Value = String> {
+    "\\.?[a-zA-Z0-9_-][a-zA-Z0-9._ -]{0,10}"
+}
+
+fn node() -> impl Strategy<Value = Node> {
+    let leaf = prop_oneof![
+        (name(), select(vec![0o644, 0o755, 0o600]))
+            .prop_map(|(name, mode)| Node::File { name, mode }),
+        (name(), name()).prop_map(|(name, target)| Node::Symlink { name, target }),
+    ];
+    leaf.prop_recursive(3, 32, 6, |inner| {
+        (name(), vec(inner, 0..6)).prop_map(|(name, children)| Node::Dir { name, children })
+    })
+}
+
+/// Create `nodes` inside the existing directory `dir`, skipping names which are already taken.
+fn materialize(dir: &Path, nodes: &[Node]) {
+    let mut seen = HashSet::new();
+    for node in nodes.iter().filter(|n| seen.insert(n.name().to_string())) {
+        let path = dir.join(node.name());
+        match node {
+            Node::File { name, mode } => common::write_file(&path, name, *mode),
+            Node::Symlink { target, .. } => symlink(target, &path).unwrap(),
+            Node::Dir { children, .. } => {
+                fs::create_dir(&path).unwrap();
+                materialize(&path, children);
+            }
+        }
+        common::set_fixture_mtime(&path);
+    }
+    common::set_fixture_mtime(dir);
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig {
+        cases: 64,
+        failure_persistence: None,
+        ..ProptestConfig::default()
+    })]
+
+    #[test]
+    fn prop_random_trees_match_gnu_ls(
+        nodes in vec(node(), 0..10),
+        flags in subsequence(FLAGS, 0..=FLAGS.len()),
+        columns in 1usize..160,
+    ) {
+        if !has_gnu_ls() {
+            return Ok(());
+        }
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("root");
+        fs::create_dir(&root).unwrap();
+        materialize(&root, &nodes);
+
+        let (rusl, ls) = outputs(tmp.path(), columns, &flags, "root");
+        prop_assert!(rusl == ls, "- ls, + rusl:\n{}", diff_lines(&ls, &rusl));
+    }
+}
@@ -0,0 +1,169 @@
+//! Runs the binary over the fixture tree from `common::fixture()` and compares its output
+//! with the files checked in under `tests/golden`.
+//!
+//! Run with `UPDATE_GOLDEN=1` to rewrite the expected outputs after an intended change.
+
+mod common;
+
+use itertools::Itertools;
+use nix::unistd::{Gid, Group, Uid, User};
+use std::env;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// A single run of the binary.
+struct Case {
+    name: String,
+    columns: usize,
+    args: Vec<&'static str>,
+}
+
+impl Case {
+    fn new(name: &str, columns: usize, args: &[&'static str]) -> Self {
+        Self {
+            name: name.to_string(),
+            columns,
+            args: args.to_vec(),
+        }
+    }
+
+    /// A case named after `mode` and every flag in `flags`, listing `operand`.
+    fn combination(
+        mode: &str,
+        columns: usize,
+        base: &[&'static str],
+        flags: &[&'static str],
+        operand: &'static str,
+    ) -> Self {
+        let name = std::iter::once(mode.to_string())
+            .chain(
+                flags
+                    .iter()
+                    .map(|f| f.trim_start_matches('-').replace('=', "")),
+            )
+            .join("_");
+        let args = [base, flags, &[operand]].concat();
+        Self {
+            name,
+            columns,
+            args,
+        }
+    }
+}
+
+fn cases() -> Vec<Case> {
+    let mut cases = Vec::new();
+    for flags in common::subsets(&["-a", "-x", "--dirs-first"]) {
+        for columns in [80, 30] {
+            let mode = format!("grid{columns}");
+            cases.push(Case::combination(&mode, columns, &[], &flags, "."));
+        }
+        cases.push(Case::combination("long", 80, &["-l"], &flags, "flat"));
+    }
+    for flags in common::subsets(&["-a", "--dirs-first", "--level=1"]) {
+        cases.push(Case::combination("tree", 80, &["--tree"], &flags, "."));
+    }
+    for flags in common::subsets(&["-a", "--dirs-first"]) {
+        cases.push(Case::combination(
+            "tree_long",
+            80,
+            &["--tree", "-l"],
+            &flags,
+            "flat",
+        ));
+    }
+    cases.extend([
+        Case::new("narrow", 1, &["flat"]),
+        Case::new("one_per_line", 80, &["-1", "flat", "docs"]),
+        Case::new("one_per_line_a", 80, &["-1", "-a", "."]),
+        Case::new(
+            "files_and_dirs",
+            80,
+            &["zeta", "docs", "flat/alpha", "flat"],
+        ),
+        Case::new("multiple_dirs", 80, &["flat", "docs", "docs/img"]),
+        Case::new(
+            "long_files",
+            80,
+            &["-l", "flat/link", "flat/run.sh", "zeta"],
+        ),
+        Case::new("missing", 80, &["nope", "zeta"]),
+        Case::new("dash_operand", 80, &["--", "-dash"]),
+        Case::new(
+            "tree_files_and_dirs",
+            80,
+            &["--tree", "zeta", "docs", "flat"],
+        ),
+    ]);
+    cases
+}
+
+/// Replace details of the output which depend on the machine running the tests.
+fn normalize(output: &str, root: &Path) -> String {
+    let meta = fs::metadata(root).unwrap();
+    let owner = User::from_uid(Uid::from_raw(meta.uid()))
+        .unwrap()
+        .map(|u| u.name)
+        .unwrap_or_default();
+    let group = Group::from_gid(Gid::from_raw(meta.gid()))
+        .unwrap()
+        .map(|g| g.name)
+        .unwrap_or_default();
+    // symlink targets are printed as absolute paths
+    let root = root.canonicalize().unwrap().display().to_string();
+    output
+        .lines()
+        .map(|line| {
+            if line.starts_with("total ") {
+                // the size of the listed directory depends on the filesystem
+                "total {total}".to_string()
+            } else {
+                line.replace(&format!(" {owner} {group} "), " {owner} {group} ")
+                    .replace(&root, "{root}")
+            }
+        })
+        .map(|line| format!("{line}\n"))
+        .collect()
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.out"))
+}
+
+#[test]
+fn test_golden_outputs() {
+    let tmp = common::fixture();
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let mut mismatches = Vec::new();
+    for case in cases() {
+        let output = common::run_rusl(tmp.path(), case.columns, &case.args);
+        let mut actual = normalize(&String::from_utf8_lossy(&output.stdout), tmp.path());
+        if !output.stderr.is_empty() {
+            actual.push_str("--- stderr ---\n");
+            actual.push_str(&String::from_utf8_lossy(&output.stderr));
+        }
+
+        let path = golden_path(&case.name);
+        if update {
+            fs::write(&path, &actual).unwrap();
+            continue;
+        }
+        let expected = fs::read_to_string(&path).unwrap_or_default();
+        if actual != expected {
+            mismatches.push(format!(
+                "{} (rusl {}):\n--- expected ---\n{expected}--- actual ---\n{actual}",
+                case.name,
+                case.args.join(" ")
+            ));
+        }
+    }
+    assert!(
+        mismatches.is_empty(),
+        "{} golden output(s) differ, rerun with UPDATE_GOLDEN=1 to accept:\n\n{}",
+        mismatches.len(),
+        mismatches.join("\n")
+    );
+}
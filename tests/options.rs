@@ -0,0 +1,48 @@
+//! Runs the binary with default options from `RUSL_OPTIONS` and checks they combine with the
+//! command line as if they were given before it.
+
+mod common;
+
+use std::process::Output;
+
+fn stdout(output: Output) -> String {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_default_options_complete_command_line() {
+    let tmp = common::fixture();
+    let run = |options: &str, args: &[&str]| {
+        stdout(common::run_rusl_with_env(
+            tmp.path(),
+            80,
+            args,
+            &[("RUSL_OPTIONS", options)],
+        ))
+    };
+    // `--level` needs `--tree`, which only the defaults give
+    let expected = stdout(common::run_rusl(tmp.path(), 80, &["--tree", "--level=1"]));
+    assert_eq!(run("--tree", &["--level=1"]), expected);
+    assert_eq!(run("--tree", &["--level", "1"]), expected);
+
+    let expected = stdout(common::run_rusl(
+        tmp.path(),
+        80,
+        &["-l", "-@", "--xattr-values"],
+    ));
+    assert_eq!(run("-l -@", &["--xattr-values"]), expected);
+
+    // without the defaults the command line alone is still rejected
+    let output = common::run_rusl_with_env(
+        tmp.path(),
+        80,
+        &["--no-config", "--level=1"],
+        &[("RUSL_OPTIONS", "--tree")],
+    );
+    assert_eq!(output.status.code(), Some(2));
+}